@@ -0,0 +1,22 @@
+//! Names of launcher features that can be queried with
+//! [`has_capability`](crate::has_capability).
+
+/// Modpacks can be zip archives with the `.hbpack` extension
+pub const MODPACK_ARCHIVES: &str = "modpack-archives";
+/// Modpacks can declare their plugins in the `plugins` section of `modpack.yaml`
+pub const PLUGIN_MANIFEST: &str = "plugin-manifest";
+/// Plugins can be signed by trusted authors
+pub const PLUGIN_SIGNATURES: &str = "plugin-signatures";
+/// Modpack files can be verified with a `checksums` file
+pub const CHECKSUMS: &str = "checksums";
+/// Plugins can register callbacks for lifecycle events, see [`events`](crate::events)
+pub const EVENTS: &str = "events";
+
+/// All capabilities of this version of the API
+pub const ALL: &[&str] = &[
+    MODPACK_ARCHIVES,
+    PLUGIN_MANIFEST,
+    PLUGIN_SIGNATURES,
+    CHECKSUMS,
+    EVENTS,
+];
@@ -0,0 +1,186 @@
+//! Lifecycle events that plugins can subscribe to with
+//! [`register_event_callback`](crate::register_event_callback), so that they don't have to hook
+//! the game themselves.
+//!
+//! The launcher owns the [`EventBus`] and fires the events from its game hooks, so that the game
+//! functions are only hooked once for all plugins. hyperbeam-essentials reports the frames spent
+//! in a dungeon.
+
+use std::os::raw::c_void;
+
+/// Identifies an event. Unknown ids are accepted so that plugins can register callbacks for events
+/// added in newer versions of the API.
+pub type HbEvent = u32;
+
+/// The modpack's plugins were loaded. Fired once, after the main functions of all plugins ran.
+pub const PLUGINS_LOADED: HbEvent = 1;
+/// The game's main loop started after the launcher was closed
+pub const GAME_FLOW_STARTED: HbEvent = 2;
+/// The player entered a dungeon
+pub const ENTERED_DUNGEON: HbEvent = 3;
+/// The player is back in town or another ground map after a dungeon
+pub const RETURNED_TO_TOWN: HbEvent = 4;
+
+/// Called with the fired event and the `user_data` passed when registering the callback.
+/// Callbacks run on the game's main thread and must not block.
+pub type HbEventCallback = extern "C" fn(event: HbEvent, user_data: *mut c_void);
+
+/// Returned when registering a callback, used to unregister it. Never 0.
+pub type HbEventHandle = u64;
+
+#[derive(Clone, Copy)]
+pub struct EventHandler {
+    pub callback: HbEventCallback,
+    pub user_data: *mut c_void,
+}
+
+impl EventHandler {
+    pub fn call(&self, event: HbEvent) {
+        (self.callback)(event, self.user_data)
+    }
+}
+
+/// Callbacks registered for events, in registration order
+#[derive(Default)]
+pub struct EventBus {
+    next_handle: HbEventHandle,
+    registrations: Vec<(HbEventHandle, HbEvent, EventHandler)>,
+}
+
+// The bus only stores `user_data`, it's up to the plugin that registered it to make it usable
+// from the game's main thread.
+unsafe impl Send for EventBus {}
+
+impl EventBus {
+    pub fn new() -> EventBus {
+        EventBus::default()
+    }
+
+    pub fn register(
+        &mut self,
+        event: HbEvent,
+        callback: HbEventCallback,
+        user_data: *mut c_void,
+    ) -> HbEventHandle {
+        self.next_handle += 1;
+        let handler = EventHandler {
+            callback,
+            user_data,
+        };
+        self.registrations.push((self.next_handle, event, handler));
+        self.next_handle
+    }
+
+    /// Removes a callback. Returns `false` if the handle isn't registered.
+    pub fn unregister(&mut self, handle: HbEventHandle) -> bool {
+        let count = self.registrations.len();
+        self.registrations
+            .retain(|(registered_handle, _, _)| *registered_handle != handle);
+        self.registrations.len() != count
+    }
+
+    /// Returns the callbacks to call for an event. They're copied so that the bus can be unlocked
+    /// while they run, which allows callbacks to register or unregister callbacks.
+    pub fn handlers(&self, event: HbEvent) -> Vec<EventHandler> {
+        self.registrations
+            .iter()
+            .filter(|(_, registered_event, _)| *registered_event == event)
+            .map(|(_, _, handler)| *handler)
+            .collect()
+    }
+}
+
+/// Where the player is, as reported by the game hooks every frame
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Location {
+    Ground,
+    Dungeon,
+}
+
+/// Turns the per-frame location reported by the game hooks into [`ENTERED_DUNGEON`] and
+/// [`RETURNED_TO_TOWN`] events, which are only fired when the location changes.
+#[derive(Debug, Default)]
+pub struct LocationTracker {
+    location: Option<Location>,
+}
+
+impl LocationTracker {
+    pub fn new() -> LocationTracker {
+        LocationTracker::default()
+    }
+
+    /// Records the current location and returns the event to fire, if any. The first ground map
+    /// after starting the game isn't a return to town.
+    pub fn update(&mut self, location: Location) -> Option<HbEvent> {
+        let previous = self.location.replace(location);
+        match (previous, location) {
+            (Some(Location::Dungeon), Location::Dungeon) => None,
+            (_, Location::Dungeon) => Some(ENTERED_DUNGEON),
+            (Some(Location::Dungeon), Location::Ground) => Some(RETURNED_TO_TOWN),
+            (_, Location::Ground) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    extern "C" fn callback(_event: HbEvent, _user_data: *mut c_void) {}
+
+    fn user_data(handler: &EventHandler) -> usize {
+        handler.user_data as usize
+    }
+
+    #[test]
+    fn handlers_in_registration_order() {
+        let mut bus = EventBus::new();
+        bus.register(ENTERED_DUNGEON, callback, 10 as *mut c_void);
+        bus.register(RETURNED_TO_TOWN, callback, 20 as *mut c_void);
+        bus.register(ENTERED_DUNGEON, callback, 30 as *mut c_void);
+
+        let handlers = bus.handlers(ENTERED_DUNGEON);
+        assert_eq!(handlers.iter().map(user_data).collect::<Vec<_>>(), [10, 30]);
+        assert_eq!(bus.handlers(RETURNED_TO_TOWN).len(), 1);
+        assert!(bus.handlers(PLUGINS_LOADED).is_empty());
+    }
+
+    #[test]
+    fn unregister() {
+        let mut bus = EventBus::new();
+        let first = bus.register(PLUGINS_LOADED, callback, ptr::null_mut());
+        let second = bus.register(PLUGINS_LOADED, callback, ptr::null_mut());
+        assert_ne!(first, 0);
+        assert_ne!(first, second);
+
+        assert!(bus.unregister(first));
+        assert!(!bus.unregister(first));
+        assert!(!bus.unregister(0));
+        assert_eq!(bus.handlers(PLUGINS_LOADED).len(), 1);
+        assert!(bus.unregister(second));
+        assert!(bus.handlers(PLUGINS_LOADED).is_empty());
+
+        // Handles aren't reused after unregistering
+        let third = bus.register(PLUGINS_LOADED, callback, ptr::null_mut());
+        assert!(third != first && third != second);
+    }
+
+    #[test]
+    fn location_changes() {
+        let mut tracker = LocationTracker::new();
+        assert_eq!(tracker.update(Location::Ground), None);
+        assert_eq!(tracker.update(Location::Ground), None);
+        assert_eq!(tracker.update(Location::Dungeon), Some(ENTERED_DUNGEON));
+        assert_eq!(tracker.update(Location::Dungeon), None);
+        assert_eq!(tracker.update(Location::Ground), Some(RETURNED_TO_TOWN));
+        assert_eq!(tracker.update(Location::Ground), None);
+    }
+
+    #[test]
+    fn dungeon_first() {
+        let mut tracker = LocationTracker::new();
+        assert_eq!(tracker.update(Location::Dungeon), Some(ENTERED_DUNGEON));
+        assert_eq!(tracker.update(Location::Ground), Some(RETURNED_TO_TOWN));
+    }
+}
@@ -0,0 +1,45 @@
+//! Functions exported by the launcher. Plugins written in other languages can declare these
+//! with the same signatures.
+
+use crate::events::{HbEvent, HbEventCallback, HbEventHandle};
+use crate::{HbModpackInfo, HbVersion};
+use std::os::raw::{c_char, c_void};
+
+extern "C" {
+    /// Returns the implemented [`API_VERSION`](crate::API_VERSION).
+    pub fn hb_api_version() -> u32;
+
+    pub fn hb_launcher_version() -> HbVersion;
+
+    /// Writes the current modpack to `out`, whose `struct_size` must be set. Only the fields that
+    /// fit are written. Returns `false` and leaves `out` unchanged if the base game was launched.
+    pub fn hb_current_modpack(out: *mut HbModpackInfo) -> bool;
+
+    /// Returns the number of enabled layers, which is 0 if the base game was launched.
+    pub fn hb_layer_count() -> usize;
+
+    /// Writes a layer to `out` like [`hb_current_modpack`], with index 0 being the highest
+    /// priority layer. Returns `false` if the index is out of range.
+    pub fn hb_layer(index: usize, out: *mut HbModpackInfo) -> bool;
+
+    /// Checks whether the launcher supports a feature. `name` is a null-terminated string.
+    pub fn hb_has_capability(name: *const c_char) -> bool;
+
+    /// Registers a callback for an event, see [`events`](crate::events). Returns a handle for
+    /// [`hb_unregister_event_callback`]. Since API version 2.
+    pub fn hb_register_event_callback(
+        event: HbEvent,
+        callback: HbEventCallback,
+        user_data: *mut c_void,
+    ) -> HbEventHandle;
+
+    /// Returns `false` if the handle isn't registered. Since API version 2.
+    pub fn hb_unregister_event_callback(handle: HbEventHandle) -> bool;
+
+    /// Calls the callbacks registered for an event. Used by hyperbeam-essentials, plugins
+    /// shouldn't fire the events defined by the API. Since API version 2.
+    ///
+    /// [`ENTERED_DUNGEON`](crate::events::ENTERED_DUNGEON) is only passed on if the player wasn't
+    /// in a dungeon already.
+    pub fn hb_fire_event(event: HbEvent);
+}
@@ -0,0 +1,279 @@
+//! Stable interface between the hyperbeam launcher and plugins.
+//!
+//! The launcher exports the `extern "C"` functions in [`ffi`], which only use the `#[repr(C)]`
+//! types of this crate, so plugins don't need to be built with the same compiler as the launcher.
+//! The safe functions in this crate wrap them for Rust plugins.
+//!
+//! New functions are only added together with a new [`API_VERSION`]. Plugins that use them should
+//! check [`api_version`] or [`has_capability`] before calling them, since they can't be resolved
+//! with older launchers.
+
+pub mod capabilities;
+pub mod events;
+pub mod ffi;
+
+use events::{HbEvent, HbEventCallback, HbEventHandle};
+use std::fmt;
+use std::os::raw::{c_char, c_void};
+use std::{mem, ptr, slice, str};
+
+/// Version of the API described by this crate. Incremented when functions are added.
+pub const API_VERSION: u32 = 2;
+
+/// A borrowed UTF-8 string that isn't null-terminated
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct HbStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl HbStr {
+    pub fn new(string: &str) -> HbStr {
+        HbStr {
+            ptr: string.as_ptr(),
+            len: string.len(),
+        }
+    }
+
+    /// # Safety
+    /// The string must point to valid UTF-8 that outlives `'a`.
+    pub unsafe fn as_str<'a>(&self) -> &'a str {
+        if self.ptr.is_null() {
+            return "";
+        }
+        str::from_utf8_unchecked(slice::from_raw_parts(self.ptr, self.len))
+    }
+}
+
+impl Default for HbStr {
+    fn default() -> HbStr {
+        HbStr::new("")
+    }
+}
+
+/// A semantic version. Pre-release and build metadata aren't included.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Ord, PartialOrd)]
+pub struct HbVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl fmt::Display for HbVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Information about a modpack. The strings are owned by the launcher and stay valid until the
+/// game exits.
+///
+/// New fields are only added at the end. Callers set `struct_size` to the size of the struct they
+/// were built with, which [`HbModpackInfo::default`] does, and the launcher only writes the fields
+/// that fit.
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct HbModpackInfo {
+    /// Size of the struct in bytes, set by the caller
+    pub struct_size: usize,
+    pub id: HbStr,
+    pub name: HbStr,
+    pub version: HbVersion,
+    /// Path of the modpack folder or archive
+    pub path: HbStr,
+}
+
+impl HbModpackInfo {
+    /// Copies the fields that fit into the `struct_size` of `out`, which is left unchanged.
+    /// Returns `false` if `out` is null or `struct_size` is too small to hold any field.
+    ///
+    /// # Safety
+    /// `out` must be null or point to a writable struct of at least `struct_size` bytes.
+    pub unsafe fn write_to(&self, out: *mut HbModpackInfo) -> bool {
+        if out.is_null() {
+            return false;
+        }
+        let header_size = mem::size_of::<usize>();
+        // Read through a pointer to the first field, `out` may be smaller than this struct
+        let size = ptr::read(out as *const usize).min(mem::size_of::<HbModpackInfo>());
+        if size <= header_size {
+            return false;
+        }
+        ptr::copy_nonoverlapping(
+            (self as *const HbModpackInfo as *const u8).add(header_size),
+            (out as *mut u8).add(header_size),
+            size - header_size,
+        );
+        true
+    }
+}
+
+impl Default for HbModpackInfo {
+    fn default() -> HbModpackInfo {
+        HbModpackInfo {
+            struct_size: mem::size_of::<HbModpackInfo>(),
+            id: HbStr::default(),
+            name: HbStr::default(),
+            version: HbVersion::default(),
+            path: HbStr::default(),
+        }
+    }
+}
+
+/// Safe version of [`HbModpackInfo`]
+#[derive(Debug, Copy, Clone)]
+pub struct ModpackInfo {
+    pub id: &'static str,
+    pub name: &'static str,
+    pub version: HbVersion,
+    pub path: &'static str,
+}
+
+impl From<HbModpackInfo> for ModpackInfo {
+    fn from(info: HbModpackInfo) -> ModpackInfo {
+        // The launcher never frees modpack information once a modpack is loaded
+        unsafe {
+            ModpackInfo {
+                id: info.id.as_str(),
+                name: info.name.as_str(),
+                version: info.version,
+                path: info.path.as_str(),
+            }
+        }
+    }
+}
+
+/// Returns the API version the launcher implements.
+pub fn api_version() -> u32 {
+    unsafe { ffi::hb_api_version() }
+}
+
+/// Returns the version of the hyperbeam launcher.
+pub fn launcher_version() -> HbVersion {
+    unsafe { ffi::hb_launcher_version() }
+}
+
+/// Returns the modpack that was launched, or `None` if the base game was launched.
+pub fn current_modpack() -> Option<ModpackInfo> {
+    let mut info = HbModpackInfo::default();
+    if unsafe { ffi::hb_current_modpack(&mut info) } {
+        Some(info.into())
+    } else {
+        None
+    }
+}
+
+/// Returns the enabled layers of the current modpack, highest priority first. The current
+/// modpack is one of them.
+pub fn layers() -> Vec<ModpackInfo> {
+    let count = unsafe { ffi::hb_layer_count() };
+    (0..count)
+        .filter_map(|index| {
+            let mut info = HbModpackInfo::default();
+            if unsafe { ffi::hb_layer(index, &mut info) } {
+                Some(info.into())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Checks whether the launcher supports a feature, see [`capabilities`].
+pub fn has_capability(name: &str) -> bool {
+    let mut name = name.as_bytes().to_vec();
+    name.push(0);
+    unsafe { ffi::hb_has_capability(name.as_ptr() as *const c_char) }
+}
+
+/// Calls `callback` with `user_data` every time `event` is fired, until it's unregistered.
+/// Requires API version 2.
+///
+/// # Safety
+/// `user_data` must stay valid until the callback is unregistered, and the callback must be able
+/// to use it from the game's main thread.
+pub unsafe fn register_event_callback(
+    event: HbEvent,
+    callback: HbEventCallback,
+    user_data: *mut c_void,
+) -> HbEventHandle {
+    ffi::hb_register_event_callback(event, callback, user_data)
+}
+
+/// Returns `false` if the callback was already unregistered. Requires API version 2.
+pub fn unregister_event_callback(handle: HbEventHandle) -> bool {
+    unsafe { ffi::hb_unregister_event_callback(handle) }
+}
+
+/// Fires an event. Requires API version 2.
+pub fn fire_event(event: HbEvent) {
+    unsafe { ffi::hb_fire_event(event) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Layout of a caller built with an older version of the struct that ends after `name`
+    #[repr(C)]
+    struct OldModpackInfo {
+        struct_size: usize,
+        id: HbStr,
+        name: HbStr,
+        guard: u64,
+    }
+
+    fn modpack_info() -> HbModpackInfo {
+        HbModpackInfo {
+            struct_size: 0,
+            id: HbStr::new("test.modpack"),
+            name: HbStr::new("Test Modpack"),
+            version: HbVersion {
+                major: 1,
+                minor: 2,
+                patch: 3,
+            },
+            path: HbStr::new("sd:/modpacks/test.modpack"),
+        }
+    }
+
+    #[test]
+    fn write_full_struct() {
+        let mut out = HbModpackInfo::default();
+        assert!(unsafe { modpack_info().write_to(&mut out) });
+        assert_eq!(out.struct_size, mem::size_of::<HbModpackInfo>());
+        let info = ModpackInfo::from(out);
+        assert_eq!(info.id, "test.modpack");
+        assert_eq!(info.name, "Test Modpack");
+        assert_eq!(info.version.to_string(), "1.2.3");
+        assert_eq!(info.path, "sd:/modpacks/test.modpack");
+    }
+
+    #[test]
+    fn write_only_fields_that_fit() {
+        let mut out = OldModpackInfo {
+            struct_size: mem::size_of::<usize>() + 2 * mem::size_of::<HbStr>(),
+            id: HbStr::default(),
+            name: HbStr::default(),
+            guard: u64::MAX,
+        };
+        let out_ptr = &mut out as *mut OldModpackInfo as *mut HbModpackInfo;
+        assert!(unsafe { modpack_info().write_to(out_ptr) });
+        assert_eq!(unsafe { out.id.as_str() }, "test.modpack");
+        assert_eq!(unsafe { out.name.as_str() }, "Test Modpack");
+        assert_eq!(out.guard, u64::MAX);
+    }
+
+    #[test]
+    fn reject_invalid_output() {
+        assert!(!unsafe { modpack_info().write_to(ptr::null_mut()) });
+        let mut out = HbModpackInfo {
+            struct_size: mem::size_of::<usize>(),
+            ..HbModpackInfo::default()
+        };
+        assert!(!unsafe { modpack_info().write_to(&mut out) });
+        assert!(unsafe { out.id.as_str() }.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use hyperbeam_rtdx::archive::to_io_error;
+use lazy_static;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Compressed entries are decompressed into memory, so larger ones are rejected
+pub const MAX_DECOMPRESSED_SIZE: u64 = 32 * 1024 * 1024;
+
+lazy_static::lazy_static! {
+    /// Archives stay open so that their central directory is only read once
+    static ref ARCHIVES: Mutex<HashMap<PathBuf, ZipArchive<File>>> = Mutex::new(HashMap::new());
+}
+
+/// Where the contents of an archive entry can be read from
+#[derive(Debug, Eq, PartialEq)]
+pub enum EntryData {
+    /// The entry is stored without compression in this range of the archive file
+    Stored { start: u64, size: u64 },
+    /// The entry is compressed and was decompressed into memory
+    Decompressed(Vec<u8>),
+}
+
+fn with_archive<T, F>(archive_path: &Path, f: F) -> io::Result<T>
+where
+    F: FnOnce(&mut ZipArchive<File>) -> io::Result<T>,
+{
+    let mut archives = ARCHIVES.lock().unwrap();
+    if !archives.contains_key(archive_path) {
+        let archive = ZipArchive::new(File::open(archive_path)?).map_err(to_io_error)?;
+        archives.insert(archive_path.to_owned(), archive);
+    }
+    f(archives.get_mut(archive_path).unwrap())
+}
+
+fn too_large_error(name: &str, size: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} is compressed and too large to be decompressed into memory ({} bytes, at most {} bytes)",
+            name, size, MAX_DECOMPRESSED_SIZE
+        ),
+    )
+}
+
+/// Returns the names of all entries in a zip archive, including directories.
+pub fn entry_names(archive_path: &Path) -> io::Result<Vec<String>> {
+    with_archive(archive_path, |archive| {
+        Ok(archive.file_names().map(str::to_owned).collect())
+    })
+}
+
+/// Reads and decompresses a single entry of a zip archive.
+pub fn read_entry(archive_path: &Path, name: &str) -> io::Result<Vec<u8>> {
+    with_archive(archive_path, |archive| {
+        let mut entry = archive.by_name(name).map_err(to_io_error)?;
+        if entry.size() > MAX_DECOMPRESSED_SIZE {
+            return Err(too_large_error(name, entry.size()));
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        Ok(data)
+    })
+}
+
+/// Locates an entry of a zip archive. Entries stored without compression can be read straight
+/// from the archive file, compressed ones are decompressed into memory.
+pub fn open_entry(archive_path: &Path, name: &str) -> io::Result<EntryData> {
+    with_archive(archive_path, |archive| {
+        let mut entry = archive.by_name(name).map_err(to_io_error)?;
+        if entry.compression() == CompressionMethod::Stored {
+            return Ok(EntryData::Stored {
+                start: entry.data_start(),
+                size: entry.size(),
+            });
+        }
+        if entry.size() > MAX_DECOMPRESSED_SIZE {
+            return Err(too_large_error(name, entry.size()));
+        }
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        Ok(EntryData::Decompressed(data))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+    use tempfile::TempDir;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    fn create_archive(path: &Path, entries: &[(&str, CompressionMethod, &[u8])]) {
+        let mut writer = ZipWriter::new(File::create(path).unwrap());
+        for (name, compression, data) in entries {
+            writer
+                .start_file(
+                    *name,
+                    FileOptions::default().compression_method(*compression),
+                )
+                .unwrap();
+            writer.write_all(data).unwrap();
+        }
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn stored_entries_are_read_from_archive() {
+        let root = TempDir::new().unwrap();
+        let archive_path = root.path().join("modpack.hbpack");
+        create_archive(
+            &archive_path,
+            &[
+                (
+                    "romfs/stored.bin",
+                    CompressionMethod::Stored,
+                    b"stored data",
+                ),
+                (
+                    "romfs/deflated.bin",
+                    CompressionMethod::Deflated,
+                    b"deflated data",
+                ),
+            ],
+        );
+
+        let (start, size) = match open_entry(&archive_path, "romfs/stored.bin").unwrap() {
+            EntryData::Stored { start, size } => (start, size),
+            data => panic!("Expected a stored entry, got {:?}", data),
+        };
+        let mut file = File::open(&archive_path).unwrap();
+        file.seek(SeekFrom::Start(start)).unwrap();
+        let mut data = vec![0; size as usize];
+        file.read_exact(&mut data).unwrap();
+        assert_eq!(data, b"stored data");
+
+        assert_eq!(
+            open_entry(&archive_path, "romfs/deflated.bin").unwrap(),
+            EntryData::Decompressed(b"deflated data".to_vec())
+        );
+        assert_eq!(
+            read_entry(&archive_path, "romfs/stored.bin").unwrap(),
+            b"stored data"
+        );
+        assert_eq!(
+            open_entry(&archive_path, "romfs/missing.bin")
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! Reports dungeon frames to the launcher, which fires the lifecycle events of `hyperbeam-api`
+//! from its own hooks.
+
+use hyperbeam_api::events;
+use pmdrtdx_bindings::*;
+use skyline::{hook, install_hooks};
+
+// Runs every frame while the player is in a dungeon, including the floor transitions. The launcher
+// only fires the event when the player wasn't in a dungeon before.
+#[hook(replace = GameFlowBlob_Dungeon_DungeonLoop_d_26_MoveNext)]
+fn hook_dungeon_loop_move_next(
+    this_ptr: *mut GameFlowBlob_Dungeon_DungeonLoop_d_26,
+    method: *mut MethodInfo,
+) -> bool {
+    hyperbeam_api::fire_event(events::ENTERED_DUNGEON);
+    call_original!(this_ptr, method)
+}
+
+pub fn install_hooks() {
+    // Launchers before API version 2 don't export `hb_fire_event`
+    if hyperbeam_api::api_version() < 2 {
+        println!("[hyperbeam-essentials] The launcher doesn't support events.");
+        return;
+    }
+    install_hooks!(hook_dungeon_loop_move_next);
+}
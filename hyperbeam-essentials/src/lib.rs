@@ -1,55 +1,240 @@
 #![feature(proc_macro_hygiene)]
 #![feature(asm)]
 
-use hyperbeam_rtdx::modpack::{ModpackMetadata, MODPACK_BASE_PATH};
+mod archive;
+mod events;
+mod memory_file;
+mod merge;
+mod overlay;
+mod patch;
+mod romfs_index;
+
+use hyperbeam_api::ModpackInfo;
+use hyperbeam_rtdx::modpack::{is_modpack_archive, ModpackLayer};
 use hyperbeam_unity::{reflect, texture_helpers, IlString};
 use lazy_static;
 use pmdrtdx_bindings::*;
 use skyline::nn;
 use skyline::{hook, install_hook, install_hooks};
+use std::cell::Cell;
+use std::error::Error;
 use std::ffi::{CString, c_void};
-use std::io::Read;
+use std::fs;
+use std::io::{self, Read};
 use std::os::raw::c_char;
+use std::path::PathBuf;
 use std::ptr::{self, null_mut};
 use std::string::String;
 use std::slice;
 use flate2::read::DeflateDecoder;
+use overlay::{LayerFile, OverlayFs, Resolution};
+use archive::EntryData;
 
 lazy_static::lazy_static! {
-    static ref MODPACK: Option<ModpackMetadata> = unsafe { hbGetCurrentModpackMetadata() };
+    static ref MODPACK: Option<ModpackInfo> = hyperbeam_api::current_modpack();
+    static ref OVERLAY: Option<OverlayFs> = MODPACK
+        .as_ref()
+        .map(|_| OverlayFs::load(&load_layers()));
+}
+
+/// nn::fs result code for a path that doesn't exist
+const RESULT_PATH_NOT_FOUND: i32 = 0x202;
+/// nn::fs result code for data that couldn't be read
+const RESULT_DATA_CORRUPTED: i32 = 0x1F4002;
+
+thread_local! {
+    /// Set while hyperbeam-essentials reads files itself, so that the file hooks don't redirect them
+    static BYPASS_FILE_HOOKS: Cell<bool> = Cell::new(false);
+}
+
+/// Reads the manifests of the current modpack's layers, highest priority first. Layers whose
+/// manifest can't be read are left out.
+fn load_layers() -> Vec<ModpackLayer> {
+    hyperbeam_api::layers()
+        .iter()
+        .filter_map(|info| match load_layer(info) {
+            Ok(layer) => Some(layer),
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-essentials] Failed to load layer {}: {}",
+                    info.path, error
+                );
+                None
+            }
+        })
+        .collect()
+}
+
+fn load_layer(info: &ModpackInfo) -> Result<ModpackLayer, Box<dyn Error>> {
+    let path = PathBuf::from(info.path);
+    let manifest = if is_modpack_archive(&path) {
+        archive::read_entry(&path, "modpack.yaml")?
+    } else {
+        fs::read(path.join("modpack.yaml"))?
+    };
+    Ok(ModpackLayer {
+        metadata: serde_yaml::from_slice(&manifest)?,
+        path,
+    })
+}
+
+/// Reads a file without redirection, e.g. to get the base game's version of a romfs file
+fn read_original_file(path: &str) -> io::Result<Vec<u8>> {
+    BYPASS_FILE_HOOKS.with(|bypass| bypass.set(true));
+    let result = fs::read(path);
+    BYPASS_FILE_HOOKS.with(|bypass| bypass.set(false));
+    result
 }
 
-extern "Rust" {
-    fn hbGetCurrentModpackMetadata() -> Option<ModpackMetadata>;
+fn build_patched_file(
+    original_path: &str,
+    base: Option<&LayerFile>,
+    patches: &[LayerFile],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let source = match base {
+        Some(base) => base.read()?,
+        None => read_original_file(original_path)?,
+    };
+    patch::apply_patch_files(source, patches)
 }
 
 #[hook(replace = nn::fs::OpenFile)]
 unsafe fn hook_open_file(handle: *mut nn::fs::FileHandle, path: *const c_char, mode: i32) -> i32 {
+    if BYPASS_FILE_HOOKS.with(Cell::get) {
+        return call_original!(handle, path, mode);
+    }
+
     let original_path = std::ffi::CStr::from_ptr(path).to_str().unwrap();
-    if original_path.starts_with("rom:/") {
-
-        // TODO: fix!!!!!!!!!!!!!!!!! (currently None)
-        let id = "techticks.testhack";//&MODPACK.as_ref().unwrap().id;
-        let new_path = format!("{}/{}/romfs/{}", MODPACK_BASE_PATH, id, &original_path[5..]);
-        println!("[hyperbeam-essentials] Trying to load: {}", new_path);
-        let new_path_cstring = CString::new(new_path).unwrap();
-        let res = call_original!(handle, new_path_cstring.as_ptr(), mode);
-        if res == 0 {
-            0
-        } else {
-            println!(
-                "[hyperbeam-essentials] Failed to load file, falling back to: {}",
-                original_path
-            );
-            call_original!(handle, path, mode)
+    if let (Some(overlay), Some(rom_path)) =
+        (OVERLAY.as_ref(), original_path.strip_prefix("rom:/"))
+    {
+        match overlay.resolve(rom_path) {
+            Some(Resolution::File(LayerFile::Path(new_path))) => {
+                println!(
+                    "[hyperbeam-essentials] Redirecting {} to {}",
+                    original_path,
+                    new_path.display()
+                );
+                let new_path_cstring = CString::new(new_path.to_str().unwrap()).unwrap();
+                return call_original!(handle, new_path_cstring.as_ptr(), mode);
+            }
+            Some(Resolution::File(archived_file)) => match open_layer_file(&archived_file) {
+                Ok(memory_file_handle) => {
+                    println!(
+                        "[hyperbeam-essentials] Redirecting {} to {}",
+                        original_path, archived_file
+                    );
+                    (*handle).handle = memory_file_handle as _;
+                    return 0;
+                }
+                Err(error) => eprintln!(
+                    "[hyperbeam-essentials] Failed to read {}, using the original file: {}",
+                    archived_file, error
+                ),
+            },
+            Some(Resolution::Deleted) => {
+                println!("[hyperbeam-essentials] Hiding deleted file {}", original_path);
+                return RESULT_PATH_NOT_FOUND;
+            }
+            Some(Resolution::Patched { base, patches }) => {
+                match build_patched_file(original_path, base.as_ref(), &patches) {
+                    Ok(data) => {
+                        println!(
+                            "[hyperbeam-essentials] Applied {} patch(es) to {}",
+                            patches.len(),
+                            original_path
+                        );
+                        (*handle).handle = memory_file::open(data) as _;
+                        return 0;
+                    }
+                    Err(error) => eprintln!(
+                        "[hyperbeam-essentials] Failed to patch {}, using the unpatched file: {}",
+                        original_path, error
+                    ),
+                }
+            }
+            None => {}
         }
-    } else {
-        call_original!(handle, path, mode)
     }
+    call_original!(handle, path, mode)
 
     // TODO: how does the game check if save data exists? does it just try to open the file?
 }
 
+/// Serves a layer file to the game as a memory file. Archive entries stored without compression
+/// are read from the archive on demand instead of being loaded into memory.
+fn open_layer_file(layer_file: &LayerFile) -> io::Result<usize> {
+    match layer_file {
+        LayerFile::Archived { archive, entry } => Ok(match archive::open_entry(archive, entry)? {
+            EntryData::Stored { start, size } => {
+                memory_file::open_range(fs::File::open(archive)?, start, size)
+            }
+            EntryData::Decompressed(data) => memory_file::open(data),
+        }),
+        LayerFile::Path(_) => Ok(memory_file::open(layer_file.read()?)),
+    }
+}
+
+fn memory_file_read_error(error: io::Error) -> i32 {
+    eprintln!(
+        "[hyperbeam-essentials] Failed to read redirected file: {}",
+        error
+    );
+    RESULT_DATA_CORRUPTED
+}
+
+#[hook(replace = nn::fs::ReadFile)]
+unsafe fn hook_read_file(
+    handle: nn::fs::FileHandle,
+    offset: i64,
+    buffer: *mut c_void,
+    size: u64,
+) -> i32 {
+    let buffer_slice = slice::from_raw_parts_mut(buffer as *mut u8, size as usize);
+    match memory_file::read(handle.handle as usize, offset as usize, buffer_slice) {
+        Some(Ok(_)) => 0,
+        Some(Err(error)) => memory_file_read_error(error),
+        None => call_original!(handle, offset, buffer, size),
+    }
+}
+
+#[hook(replace = nn::fs::ReadFile1)]
+unsafe fn hook_read_file_with_size(
+    out_size: *mut u64,
+    handle: nn::fs::FileHandle,
+    offset: i64,
+    buffer: *mut c_void,
+    size: u64,
+) -> i32 {
+    let buffer_slice = slice::from_raw_parts_mut(buffer as *mut u8, size as usize);
+    match memory_file::read(handle.handle as usize, offset as usize, buffer_slice) {
+        Some(Ok(read_size)) => {
+            *out_size = read_size as u64;
+            0
+        }
+        Some(Err(error)) => memory_file_read_error(error),
+        None => call_original!(out_size, handle, offset, buffer, size),
+    }
+}
+
+#[hook(replace = nn::fs::GetFileSize)]
+unsafe fn hook_get_file_size(out_size: *mut i64, handle: nn::fs::FileHandle) -> i32 {
+    match memory_file::size(handle.handle as usize) {
+        Some(size) => {
+            *out_size = size as i64;
+            0
+        }
+        None => call_original!(out_size, handle),
+    }
+}
+
+#[hook(replace = nn::fs::CloseFile)]
+unsafe fn hook_close_file(handle: nn::fs::FileHandle) {
+    if !memory_file::close(handle.handle as usize) {
+        call_original!(handle)
+    }
+}
+
 // TODO: add to symbol map
 #[hook(offset = 0x264B650)]
 unsafe fn hook_native_decompress_gyu0(output: *mut u8, input: *const u8, unk1: i32, unk2: *mut c_void, unk3: *mut c_void) -> i32 {
@@ -78,5 +263,28 @@ pub fn main() {
     );
 
     println!("[hyperbeam-essentials] Installing file hooks...");
-    install_hooks!(hook_open_file, hook_native_decompress_gyu0);
+    if MODPACK.is_some() {
+        println!(
+            "[hyperbeam-essentials] Modpack layers: {:?}",
+            hyperbeam_api::layers()
+                .iter()
+                .map(|layer| layer.path)
+                .collect::<Vec<_>>()
+        );
+        // Build the romfs index now instead of on the first file access
+        lazy_static::initialize(&OVERLAY);
+        install_hooks!(
+            hook_open_file,
+            hook_read_file,
+            hook_read_file_with_size,
+            hook_get_file_size,
+            hook_close_file
+        );
+    } else {
+        println!("[hyperbeam-essentials] No modpack loaded, file redirection is disabled.");
+    }
+    install_hooks!(hook_native_decompress_gyu0);
+
+    println!("[hyperbeam-essentials] Installing event hooks...");
+    events::install_hooks();
 }
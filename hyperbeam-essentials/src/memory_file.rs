@@ -0,0 +1,125 @@
+use lazy_static;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+enum Contents {
+    Data(Vec<u8>),
+    /// A range of another file, which is read on demand. The file has its own lock, since reading
+    /// it goes through the file hooks, which lock the memory files again.
+    Range {
+        file: Arc<Mutex<File>>,
+        start: u64,
+        size: u64,
+    },
+}
+
+/// A file that is served to the game from memory or from a range of another file instead of the
+/// file system, e.g. an entry of a modpack archive.
+/// Memory files are identified by the address of their boxed data, which is handed to the
+/// game in place of a real file handle. This can't collide with the handles of real files.
+struct MemoryFile {
+    contents: Contents,
+}
+
+lazy_static::lazy_static! {
+    static ref MEMORY_FILES: Mutex<HashMap<usize, Box<MemoryFile>>> = Mutex::new(HashMap::new());
+}
+
+fn insert(contents: Contents) -> usize {
+    let file = Box::new(MemoryFile { contents });
+    let handle = &*file as *const MemoryFile as usize;
+    MEMORY_FILES.lock().unwrap().insert(handle, file);
+    handle
+}
+
+/// Registers a memory file and returns the handle to give to the game.
+pub fn open(data: Vec<u8>) -> usize {
+    insert(Contents::Data(data))
+}
+
+/// Registers a memory file that reads `size` bytes of `file` starting at `start`, and returns the
+/// handle to give to the game.
+pub fn open_range(file: File, start: u64, size: u64) -> usize {
+    insert(Contents::Range {
+        file: Arc::new(Mutex::new(file)),
+        start,
+        size,
+    })
+}
+
+/// Copies file contents starting at `offset` into `buffer`.
+/// Returns the number of bytes read, or `None` if the handle doesn't belong to a memory file.
+pub fn read(handle: usize, offset: usize, buffer: &mut [u8]) -> Option<io::Result<usize>> {
+    let (file, start, size) = {
+        let files = MEMORY_FILES.lock().unwrap();
+        match &files.get(&handle)?.contents {
+            Contents::Data(data) => {
+                let start = offset.min(data.len());
+                let count = buffer.len().min(data.len() - start);
+                buffer[..count].copy_from_slice(&data[start..start + count]);
+                return Some(Ok(count));
+            }
+            Contents::Range { file, start, size } => (Arc::clone(file), *start, *size),
+        }
+    };
+
+    let offset = (offset as u64).min(size);
+    let count = (buffer.len() as u64).min(size - offset) as usize;
+    let mut file = file.lock().unwrap();
+    Some(
+        file.seek(SeekFrom::Start(start + offset))
+            .and_then(|_| file.read_exact(&mut buffer[..count]))
+            .map(|_| count),
+    )
+}
+
+pub fn size(handle: usize) -> Option<usize> {
+    MEMORY_FILES
+        .lock()
+        .unwrap()
+        .get(&handle)
+        .map(|file| match &file.contents {
+            Contents::Data(data) => data.len(),
+            Contents::Range { size, .. } => *size as usize,
+        })
+}
+
+/// Frees a memory file. Returns `false` if the handle doesn't belong to a memory file.
+pub fn close(handle: usize) -> bool {
+    MEMORY_FILES.lock().unwrap().remove(&handle).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn read_data() {
+        let handle = open(b"0123456789".to_vec());
+        let mut buffer = [0; 4];
+        assert_eq!(read(handle, 8, &mut buffer).unwrap().unwrap(), 2);
+        assert_eq!(&buffer[..2], b"89");
+        assert_eq!(size(handle), Some(10));
+        assert!(close(handle));
+        assert!(read(handle, 0, &mut buffer).is_none());
+    }
+
+    #[test]
+    fn read_range() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"header0123456789footer").unwrap();
+        let handle = open_range(file, 6, 10);
+
+        let mut buffer = [0; 4];
+        assert_eq!(read(handle, 2, &mut buffer).unwrap().unwrap(), 4);
+        assert_eq!(&buffer, b"2345");
+        assert_eq!(read(handle, 8, &mut buffer).unwrap().unwrap(), 2);
+        assert_eq!(&buffer[..2], b"89");
+        assert_eq!(read(handle, 12, &mut buffer).unwrap().unwrap(), 0);
+        assert_eq!(size(handle), Some(10));
+        assert!(close(handle));
+    }
+}
@@ -0,0 +1,524 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use serde_yaml::{Mapping, Value as YamlValue};
+use std::error::Error;
+use std::fmt;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// Format of a file that a merge patch is applied to
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MergeFormat {
+    Json,
+    Yaml,
+    Csv,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    InvalidFile(String),
+    InvalidPatch(String),
+    UnknownColumn(String),
+}
+
+impl Error for MergeError {}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeError::InvalidFile(message) => write!(f, "Failed to parse file: {}", message),
+            MergeError::InvalidPatch(message) => {
+                write!(f, "Failed to parse merge patch: {}", message)
+            }
+            MergeError::UnknownColumn(column) => write!(f, "Unknown CSV column: {}", column),
+        }
+    }
+}
+
+impl MergeFormat {
+    /// Determines the format from the name of the patched file, e.g. `table.csv`
+    pub fn from_file_name(name: &str) -> Option<MergeFormat> {
+        let extension = name.rsplit('.').next()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "json" => Some(MergeFormat::Json),
+            "yaml" | "yml" => Some(MergeFormat::Yaml),
+            "csv" => Some(MergeFormat::Csv),
+            _ => None,
+        }
+    }
+
+    /// Applies a merge patch, which is always written in YAML (or JSON, which is valid YAML).
+    pub fn apply(self, source: &[u8], patch: &[u8]) -> Result<Vec<u8>, MergeError> {
+        let (has_bom, source) = match source.strip_prefix(UTF8_BOM) {
+            Some(source) => (true, source),
+            None => (false, source),
+        };
+
+        let mut output = match self {
+            MergeFormat::Json => {
+                let mut value: Value = serde_json::from_slice(source)
+                    .map_err(|error| MergeError::InvalidFile(error.to_string()))?;
+                merge_value(&mut value, &parse_patch(patch)?);
+                if source.contains(&b'\n') {
+                    serde_json::to_vec_pretty(&value)
+                } else {
+                    serde_json::to_vec(&value)
+                }
+                .map_err(|error| MergeError::InvalidFile(error.to_string()))?
+            }
+            MergeFormat::Yaml => {
+                // YAML allows keys that aren't strings, e.g. row ids. Anchors are expanded.
+                let mut value: YamlValue = serde_yaml::from_slice(source)
+                    .map_err(|error| MergeError::InvalidFile(error.to_string()))?;
+                let patch = serde_yaml::from_slice(patch)
+                    .map_err(|error| MergeError::InvalidPatch(error.to_string()))?;
+                merge_yaml_value(&mut value, &patch);
+                serde_yaml::to_vec(&value)
+                    .map_err(|error| MergeError::InvalidFile(error.to_string()))?
+            }
+            MergeFormat::Csv => merge_csv(source, patch)?,
+        };
+
+        if has_bom {
+            output.splice(0..0, UTF8_BOM.iter().copied());
+        }
+        Ok(output)
+    }
+}
+
+fn parse_patch(patch: &[u8]) -> Result<Value, MergeError> {
+    serde_yaml::from_slice(patch).map_err(|error| MergeError::InvalidPatch(error.to_string()))
+}
+
+/// Applies a JSON merge patch (RFC 7386): objects are merged recursively, `null` removes a key
+/// and all other values replace the existing value.
+pub fn merge_value(target: &mut Value, patch: &Value) {
+    match patch {
+        Value::Object(patch_object) => {
+            if !target.is_object() {
+                *target = Value::Object(Map::new());
+            }
+            let target_object = target.as_object_mut().unwrap();
+            for (key, value) in patch_object {
+                if value.is_null() {
+                    target_object.remove(key);
+                } else {
+                    merge_value(
+                        target_object.entry(key.as_str()).or_insert(Value::Null),
+                        value,
+                    );
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+/// Applies a merge patch to a YAML value like [`merge_value`], for mappings with any type of keys.
+pub fn merge_yaml_value(target: &mut YamlValue, patch: &YamlValue) {
+    match patch {
+        YamlValue::Mapping(patch_mapping) => {
+            if !target.is_mapping() {
+                *target = YamlValue::Mapping(Mapping::new());
+            }
+            let target_mapping = target.as_mapping_mut().unwrap();
+            for (key, value) in patch_mapping {
+                if value.is_null() {
+                    target_mapping.remove(key);
+                } else if let Some(target_value) = target_mapping.get_mut(key) {
+                    merge_yaml_value(target_value, value);
+                } else {
+                    let mut target_value = YamlValue::Null;
+                    merge_yaml_value(&mut target_value, value);
+                    target_mapping.insert(key.clone(), target_value);
+                }
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ColumnRef {
+    Index(usize),
+    Name(String),
+}
+
+impl Default for ColumnRef {
+    fn default() -> Self {
+        ColumnRef::Index(0)
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Merge patch for CSV tables. Rows are identified by the value in their key column.
+///
+/// ```yaml
+/// keyColumn: 0       # column index or header name, defaults to the first column
+/// header: true       # whether the first row is a header, defaults to true
+/// rows:
+///   "12": [12, Foo, 3]       # replaces the row with key 12, or appends it if it doesn't exist
+///   "13": { 2: 5, name: x }  # changes single cells by column index or header name
+///   "14": null               # removes the row
+/// ```
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CsvMergePatch {
+    #[serde(default)]
+    key_column: ColumnRef,
+    #[serde(default = "default_true")]
+    header: bool,
+    delimiter: Option<char>,
+    #[serde(default)]
+    rows: Mapping,
+}
+
+struct CsvRecord {
+    /// The original text of the record, kept to write unchanged records exactly as they were
+    raw: Option<String>,
+    fields: Vec<String>,
+}
+
+fn parse_csv(text: &str, delimiter: char) -> Vec<CsvRecord> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut record_start = 0;
+    let mut in_quotes = false;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((index, c)) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if let Some((_, '"')) = chars.peek() {
+                    field.push('"');
+                    chars.next();
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' {
+            in_quotes = true;
+        } else if c == delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else if c == '\r' || c == '\n' {
+            fields.push(std::mem::take(&mut field));
+            records.push(CsvRecord {
+                raw: Some(text[record_start..index].to_owned()),
+                fields: std::mem::take(&mut fields),
+            });
+            if c == '\r' {
+                if let Some((_, '\n')) = chars.peek() {
+                    chars.next();
+                }
+            }
+            record_start = chars.peek().map(|(index, _)| *index).unwrap_or(text.len());
+        } else {
+            field.push(c);
+        }
+    }
+
+    if record_start < text.len() {
+        fields.push(field);
+        records.push(CsvRecord {
+            raw: Some(text[record_start..].to_owned()),
+            fields,
+        });
+    }
+    records
+}
+
+fn write_csv_field(field: &str, delimiter: char, output: &mut String) {
+    if field.contains(&[delimiter, '"', '\r', '\n'][..]) {
+        output.push('"');
+        output.push_str(&field.replace('"', "\"\""));
+        output.push('"');
+    } else {
+        output.push_str(field);
+    }
+}
+
+fn yaml_scalar_to_string(value: &YamlValue) -> Result<String, MergeError> {
+    match value {
+        YamlValue::String(string) => Ok(string.clone()),
+        YamlValue::Number(number) => Ok(number.to_string()),
+        YamlValue::Bool(boolean) => Ok(boolean.to_string()),
+        YamlValue::Null => Ok(String::new()),
+        _ => Err(MergeError::InvalidPatch(format!(
+            "Expected a single value, got {:?}",
+            value
+        ))),
+    }
+}
+
+fn merge_csv(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, MergeError> {
+    let source =
+        std::str::from_utf8(source).map_err(|error| MergeError::InvalidFile(error.to_string()))?;
+    let patch: CsvMergePatch = serde_yaml::from_slice(patch)
+        .map_err(|error| MergeError::InvalidPatch(error.to_string()))?;
+    let delimiter = patch.delimiter.unwrap_or(',');
+    let line_ending = if source.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut records = parse_csv(source, delimiter);
+    let header: Vec<String> = if patch.header {
+        records
+            .first()
+            .map(|record| record.fields.clone())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+    let column_index = |column: &ColumnRef| match column {
+        ColumnRef::Index(index) => Ok(*index),
+        ColumnRef::Name(name) => header
+            .iter()
+            .position(|header_name| header_name == name)
+            .ok_or_else(|| MergeError::UnknownColumn(name.clone())),
+    };
+    let key_column = column_index(&patch.key_column)?;
+    let first_data_record = if patch.header { 1 } else { 0 };
+
+    for (key, row) in &patch.rows {
+        let key = yaml_scalar_to_string(key)?;
+        let position = records
+            .iter()
+            .skip(first_data_record)
+            .position(|record| record.fields.get(key_column) == Some(&key))
+            .map(|position| position + first_data_record);
+
+        match (row, position) {
+            (YamlValue::Null, Some(position)) => {
+                records.remove(position);
+            }
+            (YamlValue::Null, None) => {}
+            (YamlValue::Sequence(cells), position) => {
+                let record = CsvRecord {
+                    raw: None,
+                    fields: cells
+                        .iter()
+                        .map(yaml_scalar_to_string)
+                        .collect::<Result<_, _>>()?,
+                };
+                match position {
+                    Some(position) => records[position] = record,
+                    None => records.push(record),
+                }
+            }
+            (YamlValue::Mapping(cells), position) => {
+                let position = match position {
+                    Some(position) => position,
+                    None => {
+                        let mut fields = vec![String::new(); header.len().max(key_column + 1)];
+                        fields[key_column] = key.clone();
+                        records.push(CsvRecord { raw: None, fields });
+                        records.len() - 1
+                    }
+                };
+                let record = &mut records[position];
+                record.raw = None;
+                for (column, value) in cells {
+                    let column = match column {
+                        YamlValue::Number(number) => ColumnRef::Index(
+                            number
+                                .as_u64()
+                                .ok_or_else(|| MergeError::UnknownColumn(number.to_string()))?
+                                as usize,
+                        ),
+                        other => ColumnRef::Name(yaml_scalar_to_string(other)?),
+                    };
+                    let index = column_index(&column)?;
+                    if record.fields.len() <= index {
+                        record.fields.resize(index + 1, String::new());
+                    }
+                    record.fields[index] = yaml_scalar_to_string(value)?;
+                }
+            }
+            (other, _) => {
+                return Err(MergeError::InvalidPatch(format!(
+                    "Invalid row for key {}: {:?}",
+                    key, other
+                )))
+            }
+        }
+    }
+
+    let mut output = String::with_capacity(source.len());
+    for record in &records {
+        match &record.raw {
+            Some(raw) => output.push_str(raw),
+            None => {
+                for (i, field) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        output.push(delimiter);
+                    }
+                    write_csv_field(field, delimiter, &mut output);
+                }
+            }
+        }
+        output.push_str(line_ending);
+    }
+    if !source.ends_with('\n') && output.ends_with(line_ending) {
+        output.truncate(output.len() - line_ending.len());
+    }
+
+    Ok(output.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn apply(format: MergeFormat, source: &str, patch: &str) -> String {
+        String::from_utf8(format.apply(source.as_bytes(), patch.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn merge_value_merges_objects_recursively() {
+        let mut target = json!({
+            "name": "Pikachu",
+            "stats": { "hp": 35, "attack": 55 },
+            "moves": ["Thunder Shock", "Growl"],
+        });
+        merge_value(
+            &mut target,
+            &json!({
+                "stats": { "attack": 60, "speed": 90 },
+                "moves": ["Thunderbolt"],
+            }),
+        );
+        assert_eq!(
+            target,
+            json!({
+                "name": "Pikachu",
+                "stats": { "hp": 35, "attack": 60, "speed": 90 },
+                "moves": ["Thunderbolt"],
+            })
+        );
+    }
+
+    #[test]
+    fn merge_value_null_removes_key() {
+        let mut target = json!({ "a": 1, "b": { "c": 2, "d": 3 } });
+        merge_value(
+            &mut target,
+            &json!({ "a": null, "b": { "c": null }, "e": null }),
+        );
+        assert_eq!(target, json!({ "b": { "d": 3 } }));
+    }
+
+    #[test]
+    fn merge_value_replaces_non_objects() {
+        let mut target = json!({ "a": [1, 2], "b": "text" });
+        merge_value(&mut target, &json!({ "a": { "x": 1 }, "b": 5 }));
+        assert_eq!(target, json!({ "a": { "x": 1 }, "b": 5 }));
+
+        let mut target = json!([1, 2]);
+        merge_value(&mut target, &json!({ "a": { "b": null, "c": 1 } }));
+        assert_eq!(target, json!({ "a": { "c": 1 } }));
+    }
+
+    #[test]
+    fn json_keeps_key_order_and_style() {
+        assert_eq!(
+            apply(
+                MergeFormat::Json,
+                r#"{"b":1,"a":{"y":1,"x":2}}"#,
+                "a: { x: 3, z: 4 }"
+            ),
+            r#"{"b":1,"a":{"y":1,"x":3,"z":4}}"#
+        );
+        assert_eq!(
+            apply(MergeFormat::Json, "{\n  \"a\": 1\n}", "{\"b\": [true]}"),
+            "{\n  \"a\": 1,\n  \"b\": [\n    true\n  ]\n}"
+        );
+    }
+
+    #[test]
+    fn json_keeps_bom() {
+        assert_eq!(
+            apply(MergeFormat::Json, "\u{feff}{\"a\":1}", "a: 2"),
+            "\u{feff}{\"a\":2}"
+        );
+    }
+
+    #[test]
+    fn yaml_merges_recursively() {
+        let output = apply(
+            MergeFormat::Yaml,
+            "dungeon:\n  floors: 10\n  items: [apple]\nname: Tiny Woods\n",
+            "dungeon:\n  floors: 12\n  items: null\n",
+        );
+        let value: Value = serde_yaml::from_str(&output).unwrap();
+        assert_eq!(
+            value,
+            json!({ "dungeon": { "floors": 12 }, "name": "Tiny Woods" })
+        );
+    }
+
+    #[test]
+    fn yaml_integer_keys() {
+        let output = apply(
+            MergeFormat::Yaml,
+            "1:\n  name: Bulbasaur\n  level: 5\n2:\n  name: Charmander\n3: Squirtle\n",
+            "1: { level: 16 }\n3: null\n4: Pikachu\n",
+        );
+        assert_eq!(
+            output,
+            "---\n1:\n  name: Bulbasaur\n  level: 16\n2:\n  name: Charmander\n4: Pikachu\n"
+        );
+    }
+
+    #[test]
+    fn invalid_files_and_patches() {
+        assert!(matches!(
+            MergeFormat::Json.apply(b"{", b"a: 1"),
+            Err(MergeError::InvalidFile(_))
+        ));
+        assert!(matches!(
+            MergeFormat::Yaml.apply(b"a: 1", b"a: [1"),
+            Err(MergeError::InvalidPatch(_))
+        ));
+    }
+
+    #[test]
+    fn csv_rows_by_key() {
+        let source = "id,name,level\r\n1,Bulbasaur,5\r\n2,Charmander,5\r\n3,Squirtle,5\r\n";
+        let patch = r#"
+rows:
+  "1": [1, "Ivysaur, evolved", 16]
+  "2": { level: 7, 1: Charmeleon }
+  "3": null
+  "4": [4, Pikachu, 5]
+"#;
+        assert_eq!(
+            apply(MergeFormat::Csv, source, patch),
+            "id,name,level\r\n1,\"Ivysaur, evolved\",16\r\n2,Charmeleon,7\r\n4,Pikachu,5\r\n"
+        );
+    }
+
+    #[test]
+    fn csv_key_column_without_header() {
+        let source = "a;1\nb;2";
+        let patch = "header: false\nkeyColumn: 1\ndelimiter: ';'\nrows:\n  2: { 0: c }\n";
+        assert_eq!(apply(MergeFormat::Csv, source, patch), "a;1\nc;2");
+    }
+
+    #[test]
+    fn csv_unknown_column() {
+        assert!(matches!(
+            MergeFormat::Csv.apply(b"id,name\n1,a\n", b"rows:\n  1: { level: 5 }\n"),
+            Err(MergeError::UnknownColumn(column)) if column == "level"
+        ));
+    }
+}
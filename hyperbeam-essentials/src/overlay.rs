@@ -0,0 +1,290 @@
+use crate::archive;
+use crate::romfs_index::{self, RomfsIndex};
+use hyperbeam_rtdx::modpack::ModpackLayer;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+struct Layer {
+    modpack_path: PathBuf,
+    is_archive: bool,
+    index: RomfsIndex,
+}
+
+/// A file in the romfs of a modpack layer
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LayerFile {
+    /// A file in a modpack folder
+    Path(PathBuf),
+    /// An entry in a modpack archive
+    Archived { archive: PathBuf, entry: String },
+}
+
+impl LayerFile {
+    pub fn file_name(&self) -> Option<&str> {
+        match self {
+            LayerFile::Path(path) => path.file_name().and_then(|name| name.to_str()),
+            LayerFile::Archived { entry, .. } => entry.rsplit('/').next(),
+        }
+    }
+
+    pub fn read(&self) -> io::Result<Vec<u8>> {
+        match self {
+            LayerFile::Path(path) => fs::read(path),
+            LayerFile::Archived { archive, entry } => archive::read_entry(archive, entry),
+        }
+    }
+}
+
+impl fmt::Display for LayerFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayerFile::Path(path) => write!(f, "{}", path.display()),
+            LayerFile::Archived { archive, entry } => {
+                write!(f, "{} in {}", entry, archive.display())
+            }
+        }
+    }
+}
+
+impl Layer {
+    /// Returns the layer's file for a normalized path relative to the romfs root
+    fn file(&self, rom_path: &str) -> LayerFile {
+        if self.is_archive {
+            LayerFile::Archived {
+                archive: self.modpack_path.clone(),
+                entry: format!("romfs/{}", rom_path),
+            }
+        } else {
+            LayerFile::Path(self.modpack_path.join("romfs").join(Path::new(rom_path)))
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Resolution {
+    /// The file is overridden by a modpack file
+    File(LayerFile),
+    /// The file was deleted by a modpack and should appear as missing
+    Deleted,
+    /// The file is created by applying patches to a base file
+    Patched {
+        /// The modpack file to patch, or `None` to patch the base game's file
+        base: Option<LayerFile>,
+        /// Patch files in the order they should be applied
+        patches: Vec<LayerFile>,
+    },
+}
+
+/// Resolves `rom:/` paths against a stack of modpack romfs folders and archives.
+pub struct OverlayFs {
+    /// Highest priority first
+    layers: Vec<Layer>,
+}
+
+impl OverlayFs {
+    /// Indexes the romfs of the given modpacks, which are expected in priority order (highest first).
+    pub fn load(modpack_layers: &[ModpackLayer]) -> OverlayFs {
+        let layers = modpack_layers
+            .iter()
+            .map(|modpack_layer| {
+                let is_archive = modpack_layer.is_archive();
+                let index = if is_archive {
+                    RomfsIndex::scan_archive(&modpack_layer.path)
+                } else {
+                    RomfsIndex::load_or_scan(
+                        &modpack_layer.path,
+                        &modpack_layer.metadata.version.to_string(),
+                    )
+                };
+                let mut index = index.unwrap_or_else(|error| {
+                    eprintln!(
+                        "[hyperbeam-essentials] Failed to index romfs of {}: {}",
+                        modpack_layer.path.display(),
+                        error
+                    );
+                    RomfsIndex::default()
+                });
+                for deleted_file in &modpack_layer.metadata.deleted_files {
+                    index.add_deleted(deleted_file);
+                }
+                println!(
+                    "[hyperbeam-essentials] Indexed {} files in {}",
+                    index.len(),
+                    modpack_layer.path.display()
+                );
+                Layer {
+                    modpack_path: modpack_layer.path.clone(),
+                    is_archive,
+                    index,
+                }
+            })
+            .collect();
+        OverlayFs { layers }
+    }
+
+    /// Returns how the layers provide `rom_path`, or `None` if no layer touches it and the base
+    /// game's file should be used.
+    ///
+    /// The highest priority layer that contains or deletes the file provides the base file.
+    /// A file in a layer takes precedence over a deletion in the same layer. Patches from that
+    /// layer and all layers above it are applied to the base file, starting with the lowest layer.
+    pub fn resolve(&self, rom_path: &str) -> Option<Resolution> {
+        let rom_path = romfs_index::normalize_path(rom_path);
+        let mut patches = Vec::new();
+        let mut base = None;
+
+        for layer in &self.layers {
+            patches.extend(
+                layer
+                    .index
+                    .patches(&rom_path)
+                    .iter()
+                    .rev()
+                    .map(|patch| layer.file(patch)),
+            );
+
+            if layer.index.contains(&rom_path) {
+                base = Some(layer.file(&rom_path));
+                break;
+            } else if layer.index.is_deleted(&rom_path) {
+                if !patches.is_empty() {
+                    eprintln!(
+                        "[hyperbeam-essentials] Ignoring patches for deleted file {}",
+                        rom_path
+                    );
+                }
+                return Some(Resolution::Deleted);
+            }
+        }
+
+        if patches.is_empty() {
+            base.map(Resolution::File)
+        } else {
+            patches.reverse();
+            Some(Resolution::Patched { base, patches })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Creates a modpack folder with empty romfs files at the given paths
+    fn modpack(root: &TempDir, id: &str, files: &[&str], deleted_files: &[&str]) -> ModpackLayer {
+        let path = root.path().join(id);
+        for file in files {
+            let file_path = path.join("romfs").join(file);
+            fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            fs::write(file_path, []).unwrap();
+        }
+        let metadata = format!(
+            "id: {}\nname: {}\nauthor: test\nversion: 1.0.0\ntarget: RTDX\ndeletedFiles: {:?}\n",
+            id, id, deleted_files
+        );
+        ModpackLayer {
+            metadata: serde_yaml::from_str(&metadata).unwrap(),
+            path,
+        }
+    }
+
+    fn file(layer: &ModpackLayer, rom_path: &str) -> LayerFile {
+        LayerFile::Path(layer.path.join("romfs").join(rom_path))
+    }
+
+    #[test]
+    fn untouched_file_uses_base_game() {
+        let root = TempDir::new().unwrap();
+        let layer = modpack(&root, "top", &["Data/a.bin"], &[]);
+        let overlay = OverlayFs::load(&[layer]);
+        assert_eq!(overlay.resolve("Data/b.bin"), None);
+    }
+
+    #[test]
+    fn highest_layer_provides_file() {
+        let root = TempDir::new().unwrap();
+        let top = modpack(&root, "top", &["Data/a.bin"], &[]);
+        let bottom = modpack(&root, "bottom", &["Data/a.bin", "Data/b.bin"], &[]);
+        let overlay = OverlayFs::load(&[top.clone(), bottom.clone()]);
+
+        assert_eq!(
+            overlay.resolve("Data/a.bin"),
+            Some(Resolution::File(file(&top, "Data/a.bin")))
+        );
+        assert_eq!(
+            overlay.resolve("/Data//./b.bin"),
+            Some(Resolution::File(file(&bottom, "Data/b.bin")))
+        );
+    }
+
+    #[test]
+    fn deletion_hides_lower_layers() {
+        let root = TempDir::new().unwrap();
+        let top = modpack(&root, "top", &["Data/a.bin.hbdelete"], &["Data/b.bin"]);
+        let bottom = modpack(&root, "bottom", &["Data/a.bin", "Data/b.bin"], &[]);
+        let overlay = OverlayFs::load(&[top, bottom]);
+
+        assert_eq!(overlay.resolve("Data/a.bin"), Some(Resolution::Deleted));
+        assert_eq!(overlay.resolve("Data/b.bin"), Some(Resolution::Deleted));
+    }
+
+    #[test]
+    fn file_takes_precedence_over_deletion_in_same_layer() {
+        let root = TempDir::new().unwrap();
+        let layer = modpack(&root, "top", &["Data/a.bin", "Data/a.bin.hbdelete"], &[]);
+        let overlay = OverlayFs::load(std::slice::from_ref(&layer));
+
+        assert_eq!(
+            overlay.resolve("Data/a.bin"),
+            Some(Resolution::File(file(&layer, "Data/a.bin")))
+        );
+    }
+
+    #[test]
+    fn patches_apply_from_base_layer_upwards() {
+        let root = TempDir::new().unwrap();
+        let top = modpack(&root, "top", &["Data/a.bin.ips"], &[]);
+        let middle = modpack(&root, "middle", &["Data/a.bin", "Data/a.bin.bps"], &[]);
+        let bottom = modpack(&root, "bottom", &["Data/a.bin.ips"], &[]);
+        let overlay = OverlayFs::load(&[top.clone(), middle.clone(), bottom]);
+
+        assert_eq!(
+            overlay.resolve("Data/a.bin"),
+            Some(Resolution::Patched {
+                base: Some(file(&middle, "Data/a.bin")),
+                patches: vec![
+                    file(&middle, "Data/a.bin.bps"),
+                    file(&top, "Data/a.bin.ips")
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn patches_without_base_patch_base_game() {
+        let root = TempDir::new().unwrap();
+        let layer = modpack(&root, "top", &["Data/a.bin.ips"], &[]);
+        let overlay = OverlayFs::load(std::slice::from_ref(&layer));
+
+        assert_eq!(
+            overlay.resolve("Data/a.bin"),
+            Some(Resolution::Patched {
+                base: None,
+                patches: vec![file(&layer, "Data/a.bin.ips")],
+            })
+        );
+    }
+
+    #[test]
+    fn deletion_discards_patches() {
+        let root = TempDir::new().unwrap();
+        let top = modpack(&root, "top", &["Data/a.bin.ips"], &[]);
+        let bottom = modpack(&root, "bottom", &["Data/a.bin.hbdelete"], &[]);
+        let overlay = OverlayFs::load(&[top, bottom]);
+
+        assert_eq!(overlay.resolve("Data/a.bin"), Some(Resolution::Deleted));
+    }
+}
@@ -0,0 +1,525 @@
+use crate::merge::MergeFormat;
+use crate::overlay::LayerFile;
+use flate2::Crc;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+    /// Structured merge patch, see the [`merge`](crate::merge) module
+    Merge(MergeFormat),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PatchError {
+    InvalidHeader,
+    UnexpectedEof,
+    OutOfBounds,
+    SourceSizeMismatch { expected: u64, actual: u64 },
+    ChecksumMismatch(&'static str),
+}
+
+impl Error for PatchError {}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatchError::InvalidHeader => write!(f, "Invalid patch header"),
+            PatchError::UnexpectedEof => write!(f, "Unexpected end of patch"),
+            PatchError::OutOfBounds => write!(f, "Patch reads outside of the file"),
+            PatchError::SourceSizeMismatch { expected, actual } => write!(
+                f,
+                "Patch expects a file of {} bytes, but the file has {} bytes",
+                expected, actual
+            ),
+            PatchError::ChecksumMismatch(name) => write!(f, "{} checksum mismatch", name),
+        }
+    }
+}
+
+impl PatchFormat {
+    /// Determines the format of a patch file from its name, e.g. `foo.bin.ips` or `foo.csv.hbmerge`.
+    /// Returns the name of the patched file and the format.
+    pub fn from_file_name(name: &str) -> Option<(&str, PatchFormat)> {
+        if let Some(target) = name.strip_suffix(".ips") {
+            Some((target, PatchFormat::Ips))
+        } else if let Some(target) = name.strip_suffix(".bps") {
+            Some((target, PatchFormat::Bps))
+        } else if let Some(target) = name.strip_suffix(".hbmerge") {
+            MergeFormat::from_file_name(target).map(|format| (target, PatchFormat::Merge(format)))
+        } else {
+            None
+        }
+    }
+
+    /// Binary patches depend on exact offsets, so they're applied before merge patches
+    /// from the same modpack.
+    pub fn is_binary(self) -> bool {
+        !matches!(self, PatchFormat::Merge(_))
+    }
+
+    pub fn apply(self, source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match self {
+            PatchFormat::Ips => apply_ips(source, patch)?,
+            PatchFormat::Bps => apply_bps(source, patch)?,
+            PatchFormat::Merge(format) => format.apply(source, patch)?,
+        })
+    }
+}
+
+/// Applies patch files in order. The format of each patch is determined from its file name.
+pub fn apply_patch_files(
+    source: Vec<u8>,
+    patch_files: &[LayerFile],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = source;
+    for patch_file in patch_files {
+        let format = patch_file
+            .file_name()
+            .and_then(PatchFormat::from_file_name)
+            .map(|(_, format)| format)
+            .ok_or(PatchError::InvalidHeader)?;
+        let patch = patch_file.read()?;
+        data = format.apply(&data, &patch)?;
+    }
+    Ok(data)
+}
+
+struct PatchReader<'a> {
+    patch: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> Result<&'a [u8], PatchError> {
+        let end = self
+            .position
+            .checked_add(count)
+            .ok_or(PatchError::UnexpectedEof)?;
+        let bytes = self
+            .patch
+            .get(self.position..end)
+            .ok_or(PatchError::UnexpectedEof)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, PatchError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_be(&mut self, count: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .read_bytes(count)?
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as usize))
+    }
+
+    fn read_varint(&mut self) -> Result<u64, PatchError> {
+        let mut data: u64 = 0;
+        let mut shift: u64 = 1;
+        loop {
+            let byte = self.read_u8()?;
+            data = ((byte & 0x7f) as u64)
+                .checked_mul(shift)
+                .and_then(|value| data.checked_add(value))
+                .ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            data = data.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+}
+
+/// Applies an IPS patch, including the optional truncation extension.
+pub fn apply_ips(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = PatchReader { patch, position: 0 };
+    if reader.read_bytes(5)? != b"PATCH" {
+        return Err(PatchError::InvalidHeader);
+    }
+
+    let mut output = source.to_vec();
+    loop {
+        let offset_bytes = reader.read_bytes(3)?;
+        if offset_bytes == b"EOF" {
+            break;
+        }
+        let offset = offset_bytes
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as usize);
+
+        let size = reader.read_be(2)?;
+        let (length, fill_value) = if size == 0 {
+            // Run-length encoded record
+            let length = reader.read_be(2)?;
+            (length, Some(reader.read_u8()?))
+        } else {
+            (size, None)
+        };
+
+        let end = offset + length;
+        if output.len() < end {
+            output.resize(end, 0);
+        }
+        match fill_value {
+            Some(value) => output[offset..end].iter_mut().for_each(|b| *b = value),
+            None => output[offset..end].copy_from_slice(reader.read_bytes(length)?),
+        }
+    }
+
+    if let Ok(truncate_size) = reader.read_be(3) {
+        output.truncate(truncate_size);
+    }
+
+    Ok(output)
+}
+
+const BPS_FOOTER_SIZE: usize = 12;
+
+/// Applies a BPS patch and verifies the source, target and patch checksums.
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < 4 + BPS_FOOTER_SIZE {
+        return Err(PatchError::UnexpectedEof);
+    }
+    let footer_start = patch.len() - BPS_FOOTER_SIZE;
+    let checksum = |offset: usize| {
+        u32::from_le_bytes(
+            patch[footer_start + offset..footer_start + offset + 4]
+                .try_into()
+                .unwrap(),
+        )
+    };
+    let crc32 = |data: &[u8]| {
+        let mut crc = Crc::new();
+        crc.update(data);
+        crc.sum()
+    };
+
+    if crc32(&patch[..patch.len() - 4]) != checksum(8) {
+        return Err(PatchError::ChecksumMismatch("Patch"));
+    }
+    if crc32(source) != checksum(0) {
+        return Err(PatchError::ChecksumMismatch("Source"));
+    }
+
+    let mut reader = PatchReader {
+        patch: &patch[..footer_start],
+        position: 0,
+    };
+    if reader.read_bytes(4)? != b"BPS1" {
+        return Err(PatchError::InvalidHeader);
+    }
+
+    let source_size = reader.read_varint()?;
+    if source_size != source.len() as u64 {
+        return Err(PatchError::SourceSizeMismatch {
+            expected: source_size,
+            actual: source.len() as u64,
+        });
+    }
+    let target_size = reader.read_varint()? as usize;
+    let metadata_size = reader.read_varint()? as usize;
+    reader.read_bytes(metadata_size)?;
+
+    // The target size isn't verified until the whole patch was applied, so a corrupt size must
+    // not cause a huge allocation
+    let mut output: Vec<u8> = Vec::with_capacity(target_size.min(source.len() + patch.len()));
+    let mut source_relative_offset: i64 = 0;
+    let mut target_relative_offset: i64 = 0;
+
+    let read_signed = |reader: &mut PatchReader| -> Result<i64, PatchError> {
+        let data = reader.read_varint()?;
+        let value = (data >> 1) as i64;
+        Ok(if data & 1 != 0 { -value } else { value })
+    };
+
+    while reader.position < reader.patch.len() {
+        let data = reader.read_varint()?;
+        let command = data & 3;
+        let length = ((data >> 2) + 1) as usize;
+        if output.len() + length > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+
+        match command {
+            // SourceRead
+            0 => {
+                let bytes = source
+                    .get(output.len()..)
+                    .and_then(|bytes| bytes.get(..length))
+                    .ok_or(PatchError::OutOfBounds)?;
+                output.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => output.extend_from_slice(reader.read_bytes(length)?),
+            // SourceCopy
+            2 => {
+                source_relative_offset = source_relative_offset
+                    .checked_add(read_signed(&mut reader)?)
+                    .filter(|offset| *offset >= 0)
+                    .ok_or(PatchError::OutOfBounds)?;
+                let bytes = source
+                    .get(source_relative_offset as usize..)
+                    .and_then(|bytes| bytes.get(..length))
+                    .ok_or(PatchError::OutOfBounds)?;
+                output.extend_from_slice(bytes);
+                source_relative_offset += length as i64;
+            }
+            // TargetCopy
+            _ => {
+                target_relative_offset = target_relative_offset
+                    .checked_add(read_signed(&mut reader)?)
+                    .filter(|offset| *offset >= 0 && (*offset as usize) < output.len())
+                    .ok_or(PatchError::OutOfBounds)?;
+                // The copied range may overlap with the bytes being written, so copy byte by byte
+                for _ in 0..length {
+                    let byte = output[target_relative_offset as usize];
+                    output.push(byte);
+                    target_relative_offset += 1;
+                }
+            }
+        }
+    }
+
+    if output.len() != target_size {
+        return Err(PatchError::UnexpectedEof);
+    }
+    if crc32(&output) != checksum(4) {
+        return Err(PatchError::ChecksumMismatch("Target"));
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips_patch(records: &[u8], truncate_size: Option<usize>) -> Vec<u8> {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(records);
+        patch.extend_from_slice(b"EOF");
+        if let Some(size) = truncate_size {
+            patch.extend_from_slice(&(size as u32).to_be_bytes()[1..]);
+        }
+        patch
+    }
+
+    #[test]
+    fn ips_record() {
+        let patch = ips_patch(b"\x00\x00\x02\x00\x02xy", None);
+        assert_eq!(apply_ips(b"ABCDEF", &patch).unwrap(), b"ABxyEF");
+    }
+
+    #[test]
+    fn ips_rle_record() {
+        let patch = ips_patch(b"\x00\x00\x01\x00\x00\x00\x03z", None);
+        assert_eq!(apply_ips(b"ABCDEF", &patch).unwrap(), b"AzzzEF");
+    }
+
+    #[test]
+    fn ips_record_past_end_extends_file() {
+        let patch = ips_patch(b"\x00\x00\x08\x00\x02GH", None);
+        assert_eq!(apply_ips(b"ABCDEF", &patch).unwrap(), b"ABCDEF\0\0GH");
+    }
+
+    #[test]
+    fn ips_truncation() {
+        let patch = ips_patch(b"\x00\x00\x00\x00\x01x", Some(4));
+        assert_eq!(apply_ips(b"ABCDEF", &patch).unwrap(), b"xBCD");
+    }
+
+    #[test]
+    fn ips_invalid_patches() {
+        assert_eq!(
+            apply_ips(b"ABCDEF", b"PATCX\x00\x00\x00EOF"),
+            Err(PatchError::InvalidHeader)
+        );
+        assert_eq!(
+            apply_ips(b"ABCDEF", b"PATCH\x00\x00\x00\x00\x05xy"),
+            Err(PatchError::UnexpectedEof)
+        );
+    }
+
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = Crc::new();
+        crc.update(data);
+        crc.sum()
+    }
+
+    fn varint(mut data: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (data & 0x7f) as u8;
+            data >>= 7;
+            if data == 0 {
+                bytes.push(0x80 | byte);
+                return bytes;
+            }
+            bytes.push(byte);
+            data -= 1;
+        }
+    }
+
+    fn command(command: u64, length: u64) -> Vec<u8> {
+        varint(((length - 1) << 2) | command)
+    }
+
+    fn offset(offset: i64) -> Vec<u8> {
+        varint((offset.unsigned_abs() << 1) | (offset < 0) as u64)
+    }
+
+    /// Builds a BPS patch with valid checksums from encoded commands
+    fn bps_patch(source: &[u8], target: &[u8], target_size: u64, commands: &[Vec<u8>]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(varint(source.len() as u64));
+        patch.extend(varint(target_size));
+        patch.extend(varint(0));
+        for command in commands {
+            patch.extend_from_slice(command);
+        }
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    const SOURCE: &[u8] = b"ABCDEFGH";
+    const TARGET: &[u8] = b"ABxyzFGHxyzFFFF";
+
+    fn all_commands_patch() -> Vec<u8> {
+        let commands = [
+            // SourceRead "AB"
+            command(0, 2),
+            // TargetRead "xyz"
+            command(1, 3),
+            b"xyz".to_vec(),
+            // SourceCopy "FGH"
+            command(2, 3),
+            offset(5),
+            // TargetCopy "xyzF"
+            command(3, 4),
+            offset(2),
+            // TargetCopy overlapping with its output, repeating the last "F"
+            command(3, 3),
+            offset(5),
+        ];
+        bps_patch(SOURCE, TARGET, TARGET.len() as u64, &commands)
+    }
+
+    #[test]
+    fn bps_all_commands() {
+        assert_eq!(apply_bps(SOURCE, &all_commands_patch()).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn bps_checksum_mismatch() {
+        assert_eq!(
+            apply_bps(b"ABCDEFGX", &all_commands_patch()),
+            Err(PatchError::ChecksumMismatch("Source"))
+        );
+
+        let mut patch = all_commands_patch();
+        patch[8] = b'w';
+        assert_eq!(
+            apply_bps(SOURCE, &patch),
+            Err(PatchError::ChecksumMismatch("Patch"))
+        );
+
+        let patch = bps_patch(
+            SOURCE,
+            b"ABxyzFGHxyzFFFX",
+            15,
+            &[command(0, 8), command(1, 7), b"xyzFFFF".to_vec()],
+        );
+        assert_eq!(
+            apply_bps(SOURCE, &patch),
+            Err(PatchError::ChecksumMismatch("Target"))
+        );
+    }
+
+    #[test]
+    fn bps_source_size_mismatch() {
+        let patch = all_commands_patch();
+        let mut source = SOURCE.to_vec();
+        source.push(b'I');
+        let mut patch_for_longer_source = patch[..patch.len() - BPS_FOOTER_SIZE].to_vec();
+        patch_for_longer_source.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch_for_longer_source.extend_from_slice(&crc32(TARGET).to_le_bytes());
+        let patch_checksum = crc32(&patch_for_longer_source);
+        patch_for_longer_source.extend_from_slice(&patch_checksum.to_le_bytes());
+        assert_eq!(
+            apply_bps(&source, &patch_for_longer_source),
+            Err(PatchError::SourceSizeMismatch {
+                expected: 8,
+                actual: 9
+            })
+        );
+    }
+
+    #[test]
+    fn bps_out_of_bounds() {
+        // SourceRead past the end of the source
+        let patch = bps_patch(b"AB", b"ABCD", 4, &[command(0, 4)]);
+        assert_eq!(apply_bps(b"AB", &patch), Err(PatchError::OutOfBounds));
+
+        let patches = [
+            // SourceCopy before the start of the source
+            bps_patch(SOURCE, b"AB", 2, &[command(2, 2), offset(-1)]),
+            // SourceCopy past the end of the source
+            bps_patch(SOURCE, b"AB", 2, &[command(2, 2), offset(i64::MAX)]),
+            // TargetCopy without any output yet
+            bps_patch(SOURCE, b"AB", 2, &[command(3, 2), offset(0)]),
+            // TargetCopy after the end of the output
+            bps_patch(
+                SOURCE,
+                b"ABAB",
+                4,
+                &[command(0, 2), command(3, 2), offset(2)],
+            ),
+            // More output than the target size
+            bps_patch(SOURCE, b"AB", 2, &[command(0, 3)]),
+        ];
+        for patch in &patches {
+            assert_eq!(apply_bps(SOURCE, patch), Err(PatchError::OutOfBounds));
+        }
+    }
+
+    #[test]
+    fn bps_invalid_patches() {
+        // The relative offset overflows after the first copy
+        let patch = bps_patch(
+            SOURCE,
+            b"AB",
+            2,
+            &[command(2, 1), offset(0), command(2, 1), offset(i64::MAX)],
+        );
+        assert_eq!(apply_bps(SOURCE, &patch), Err(PatchError::OutOfBounds));
+
+        // Varints can't overflow
+        let mut patch = b"BPS1".to_vec();
+        patch.extend_from_slice(&[0; 10]);
+        patch.push(0x80);
+        patch.extend_from_slice(&crc32(SOURCE).to_le_bytes());
+        patch.extend_from_slice(&crc32(b"").to_le_bytes());
+        let patch_checksum = crc32(&patch);
+        patch.extend_from_slice(&patch_checksum.to_le_bytes());
+        assert_eq!(apply_bps(SOURCE, &patch), Err(PatchError::OutOfBounds));
+
+        // A huge target size isn't allocated up front
+        let patch = bps_patch(SOURCE, b"AB", u64::MAX >> 8, &[command(0, 2)]);
+        assert_eq!(apply_bps(SOURCE, &patch), Err(PatchError::UnexpectedEof));
+
+        let patch = bps_patch(SOURCE, b"", 0, &[]);
+        assert_eq!(
+            apply_bps(SOURCE, &patch[..patch.len() - 1]),
+            Err(PatchError::ChecksumMismatch("Patch"))
+        );
+        assert_eq!(apply_bps(SOURCE, b"BPS1"), Err(PatchError::UnexpectedEof));
+    }
+}
@@ -0,0 +1,311 @@
+use crate::archive;
+use crate::patch::PatchFormat;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// Name of the index cache file, stored next to `modpack.yaml`
+pub const CACHE_FILE_NAME: &str = "romfs_index.cache";
+const CACHE_HEADER: &str = "hyperbeam-romfs-index 3";
+/// Extension of marker files that hide the file with the same name from the game
+pub const DELETE_MARKER_EXTENSION: &str = ".hbdelete";
+
+/// Set of all files a modpack's romfs folder overrides or deletes, relative to the romfs root.
+#[derive(Debug, Default)]
+pub struct RomfsIndex {
+    files: HashSet<String>,
+    deleted: HashSet<String>,
+    /// Patch files, by the path of the file they patch
+    patches: HashMap<String, Vec<String>>,
+}
+
+/// Converts a path inside the romfs to the form used as index key, e.g. `/Data//a/./b` to `Data/a/b`.
+pub fn normalize_path(path: &str) -> String {
+    path.split(|c| c == '/' || c == '\\')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+impl RomfsIndex {
+    /// Walks the romfs folder and records every file in it.
+    pub fn scan(romfs_path: &Path) -> io::Result<RomfsIndex> {
+        fn visit(dir: &Path, prefix: &str, index: &mut RomfsIndex) -> io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name();
+                let name = match name.to_str() {
+                    Some(name) => name,
+                    None => continue,
+                };
+                let relative_path = if prefix.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{}/{}", prefix, name)
+                };
+
+                let path = entry.path();
+                if path.is_dir() {
+                    visit(&path, &relative_path, index)?;
+                } else if let Some(deleted_path) =
+                    relative_path.strip_suffix(DELETE_MARKER_EXTENSION)
+                {
+                    index.deleted.insert(deleted_path.to_owned());
+                } else {
+                    index.files.insert(relative_path);
+                }
+            }
+            Ok(())
+        }
+
+        let mut index = RomfsIndex::default();
+        if romfs_path.is_dir() {
+            visit(romfs_path, "", &mut index)?;
+        }
+        index.index_patches();
+        Ok(index)
+    }
+
+    /// Records every file in the `romfs` folder of a modpack archive.
+    pub fn scan_archive(archive_path: &Path) -> io::Result<RomfsIndex> {
+        let mut index = RomfsIndex::default();
+        for name in archive::entry_names(archive_path)? {
+            if name.ends_with('/') {
+                continue;
+            }
+            let relative_path = match name.strip_prefix("romfs/") {
+                Some(relative_path) => normalize_path(relative_path),
+                None => continue,
+            };
+            if let Some(deleted_path) = relative_path.strip_suffix(DELETE_MARKER_EXTENSION) {
+                index.deleted.insert(deleted_path.to_owned());
+            } else {
+                index.files.insert(relative_path);
+            }
+        }
+        index.index_patches();
+        Ok(index)
+    }
+
+    /// Loads the cached index of a modpack if it was written for the same modpack version and no
+    /// folder in the romfs was modified since, otherwise scans the romfs folder and updates the
+    /// cache.
+    ///
+    /// Adding, removing or renaming a file changes the modification time of its folder, so the
+    /// latest modification time of all folders changes with every file in the index.
+    pub fn load_or_scan(modpack_path: &Path, modpack_version: &str) -> io::Result<RomfsIndex> {
+        let romfs_path = modpack_path.join("romfs");
+        let cache_path = modpack_path.join(CACHE_FILE_NAME);
+        let cache_key = latest_modification_time(&romfs_path)
+            .map(|modified| format!("{} {}", modpack_version, modified));
+
+        if let Some(cache_key) = &cache_key {
+            match RomfsIndex::load_cache(&cache_path, cache_key) {
+                Ok(Some(index)) => return Ok(index),
+                Ok(None) => {}
+                Err(error) => eprintln!(
+                    "[hyperbeam-essentials] Failed to read romfs index cache {}: {}",
+                    cache_path.display(),
+                    error
+                ),
+            }
+        }
+
+        let index = RomfsIndex::scan(&romfs_path)?;
+        if let Some(cache_key) = &cache_key {
+            if let Err(error) = index.save_cache(&cache_path, cache_key) {
+                eprintln!(
+                    "[hyperbeam-essentials] Failed to write romfs index cache {}: {}",
+                    cache_path.display(),
+                    error
+                );
+            }
+        }
+        Ok(index)
+    }
+
+    /// Reads a cache file. Returns `None` if the file doesn't exist or was written with another key.
+    pub fn load_cache(cache_path: &Path, cache_key: &str) -> io::Result<Option<RomfsIndex>> {
+        let file = match fs::File::open(cache_path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let mut lines = BufReader::new(file).lines();
+
+        if lines.next().transpose()?.as_deref() != Some(CACHE_HEADER) {
+            return Ok(None);
+        }
+        if lines.next().transpose()?.as_deref() != Some(cache_key) {
+            return Ok(None);
+        }
+
+        let mut index = RomfsIndex::default();
+        for line in lines {
+            let line = line?;
+            if let Some(path) = line.strip_prefix("F ") {
+                index.files.insert(path.to_owned());
+            } else if let Some(path) = line.strip_prefix("D ") {
+                index.deleted.insert(path.to_owned());
+            }
+        }
+        index.index_patches();
+        Ok(Some(index))
+    }
+
+    pub fn save_cache(&self, cache_path: &Path, cache_key: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(fs::File::create(cache_path)?);
+        writeln!(writer, "{}", CACHE_HEADER)?;
+        writeln!(writer, "{}", cache_key)?;
+        for file in &self.files {
+            writeln!(writer, "F {}", file)?;
+        }
+        for file in &self.deleted {
+            writeln!(writer, "D {}", file)?;
+        }
+        writer.flush()
+    }
+
+    fn index_patches(&mut self) {
+        self.patches.clear();
+        for file in &self.files {
+            if let Some((target, _)) = PatchFormat::from_file_name(file) {
+                self.patches
+                    .entry(target.to_owned())
+                    .or_insert_with(Vec::new)
+                    .push(file.clone());
+            }
+        }
+        for patches in self.patches.values_mut() {
+            patches.sort_by_key(|patch| {
+                let is_binary = PatchFormat::from_file_name(patch)
+                    .map(|(_, format)| format.is_binary())
+                    .unwrap_or(false);
+                (!is_binary, patch.clone())
+            });
+        }
+    }
+
+    /// Marks a file as deleted in addition to the `.hbdelete` markers found in the romfs.
+    pub fn add_deleted(&mut self, path: &str) {
+        self.deleted.insert(normalize_path(path));
+    }
+
+    /// Checks whether the romfs overrides a file. `path` must be normalized with [`normalize_path`].
+    pub fn contains(&self, path: &str) -> bool {
+        self.files.contains(path)
+    }
+
+    /// Checks whether the romfs deletes a file. `path` must be normalized with [`normalize_path`].
+    pub fn is_deleted(&self, path: &str) -> bool {
+        self.deleted.contains(path)
+    }
+
+    /// Returns the patch files for a file in the order they should be applied.
+    /// `path` must be normalized with [`normalize_path`].
+    pub fn patches(&self, path: &str) -> &[String] {
+        self.patches.get(path).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+}
+
+fn modification_time(path: &Path) -> Option<u128> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_nanos())
+}
+
+/// Returns the latest modification time of a folder and all folders inside it. Only folders are
+/// visited, which is cheaper than scanning the files.
+fn latest_modification_time(dir: &Path) -> Option<u128> {
+    let mut latest = modification_time(dir)?;
+    for entry in fs::read_dir(dir).ok()? {
+        let path = entry.ok()?.path();
+        if path.is_dir() {
+            latest = latest.max(latest_modification_time(&path)?);
+        }
+    }
+    Some(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_file(path: &Path) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, []).unwrap();
+    }
+
+    #[test]
+    fn cache_is_reused_for_same_version() {
+        let modpack = TempDir::new().unwrap();
+        create_file(&modpack.path().join("romfs/Data/a.bin"));
+        RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+
+        // The cached index is used as long as the romfs isn't modified
+        let cache_path = modpack.path().join(CACHE_FILE_NAME);
+        let mut cache = fs::read_to_string(&cache_path).unwrap();
+        cache.push_str("F Data/cached.bin\n");
+        fs::write(&cache_path, cache).unwrap();
+        let index = RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+        assert!(index.contains("Data/a.bin"));
+        assert!(index.contains("Data/cached.bin"));
+    }
+
+    #[test]
+    fn cache_is_invalidated_by_nested_file() {
+        let modpack = TempDir::new().unwrap();
+        create_file(&modpack.path().join("romfs/Data/Sub/a.bin"));
+        RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+
+        // Only the modification time of the folder that contains the file changes
+        create_file(&modpack.path().join("romfs/Data/Sub/b.bin"));
+        let index = RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+        assert!(index.contains("Data/Sub/a.bin"));
+        assert!(index.contains("Data/Sub/b.bin"));
+
+        fs::remove_file(modpack.path().join("romfs/Data/Sub/a.bin")).unwrap();
+        let index = RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+        assert!(!index.contains("Data/Sub/a.bin"));
+    }
+
+    #[test]
+    fn cache_is_invalidated_by_new_version() {
+        let modpack = TempDir::new().unwrap();
+        create_file(&modpack.path().join("romfs/Data/a.bin"));
+        RomfsIndex::load_or_scan(modpack.path(), "1.0.0").unwrap();
+
+        create_file(&modpack.path().join("romfs/Data/b.bin"));
+        let index = RomfsIndex::load_or_scan(modpack.path(), "1.1.0").unwrap();
+        assert!(index.contains("Data/a.bin"));
+        assert!(index.contains("Data/b.bin"));
+    }
+
+    #[test]
+    fn cache_round_trips() {
+        let modpack = TempDir::new().unwrap();
+        create_file(&modpack.path().join("romfs/Data/a.bin"));
+        create_file(&modpack.path().join("romfs/Data/a.bin.ips"));
+        create_file(&modpack.path().join("romfs/Data/b.bin.hbdelete"));
+        let index = RomfsIndex::scan(&modpack.path().join("romfs")).unwrap();
+        let cache_path = modpack.path().join(CACHE_FILE_NAME);
+        index.save_cache(&cache_path, "key").unwrap();
+
+        assert!(RomfsIndex::load_cache(&cache_path, "other key")
+            .unwrap()
+            .is_none());
+        let cached = RomfsIndex::load_cache(&cache_path, "key").unwrap().unwrap();
+        assert!(cached.contains("Data/a.bin"));
+        assert!(cached.is_deleted("Data/b.bin"));
+        assert_eq!(cached.patches("Data/a.bin"), ["Data/a.bin.ips"]);
+    }
+}
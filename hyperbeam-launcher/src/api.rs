@@ -0,0 +1,132 @@
+//! Implementation of the functions of the `hyperbeam-api` crate, which plugins call to get
+//! information about the launcher and the loaded modpack.
+
+use crate::self_update;
+use hyperbeam_api::events::{
+    self, EventBus, HbEvent, HbEventCallback, HbEventHandle, Location, LocationTracker,
+};
+use hyperbeam_api::{capabilities, HbModpackInfo, HbStr, HbVersion, API_VERSION};
+use hyperbeam_rtdx::modpack::{CurrentModpack, ModpackMetadata};
+use semver::Version;
+use std::ffi::CStr;
+use std::os::raw::{c_char, c_void};
+use std::path::Path;
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    static ref EVENT_BUS: Mutex<EventBus> = Mutex::new(EventBus::new());
+    static ref LOCATION: Mutex<LocationTracker> = Mutex::new(LocationTracker::new());
+}
+
+fn current_modpack() -> Option<&'static CurrentModpack> {
+    unsafe { crate::GLOBALS.loaded_modpack.as_ref() }
+}
+
+/// Calls the callbacks registered for an event
+pub fn fire_event(event: HbEvent) {
+    // The bus is unlocked while the callbacks run, so that they can register other callbacks
+    let handlers = EVENT_BUS.lock().unwrap().handlers(event);
+    for handler in handlers {
+        handler.call(event);
+    }
+}
+
+/// Called every frame with the player's location, fires an event when it changes
+pub fn update_location(location: Location) {
+    // The tracker is unlocked before the callbacks run
+    let event = LOCATION.lock().unwrap().update(location);
+    if let Some(event) = event {
+        println!("[hyperbeam-launcher] Firing event {}", event);
+        fire_event(event);
+    }
+}
+
+fn to_hb_version(version: &Version) -> HbVersion {
+    HbVersion {
+        major: version.major,
+        minor: version.minor,
+        patch: version.patch,
+    }
+}
+
+fn modpack_info(metadata: &ModpackMetadata, path: &Path) -> HbModpackInfo {
+    HbModpackInfo {
+        id: HbStr::new(&metadata.id),
+        name: HbStr::new(&metadata.name),
+        version: to_hb_version(&metadata.version),
+        path: HbStr::new(path.to_str().unwrap_or_default()),
+        ..HbModpackInfo::default()
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn hb_api_version() -> u32 {
+    API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn hb_launcher_version() -> HbVersion {
+    Version::parse(self_update::VERSION)
+        .map(|version| to_hb_version(&version))
+        .unwrap_or_default()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn hb_current_modpack(out: *mut HbModpackInfo) -> bool {
+    match current_modpack() {
+        Some(modpack) => modpack_info(&modpack.metadata, &modpack.path).write_to(out),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn hb_layer_count() -> usize {
+    current_modpack()
+        .map(|modpack| modpack.layers.len())
+        .unwrap_or(0)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn hb_layer(index: usize, out: *mut HbModpackInfo) -> bool {
+    match current_modpack().and_then(|modpack| modpack.layers.get(index)) {
+        Some(layer) => modpack_info(&layer.metadata, &layer.path).write_to(out),
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn hb_has_capability(name: *const c_char) -> bool {
+    if name.is_null() {
+        return false;
+    }
+    match CStr::from_ptr(name).to_str() {
+        Ok(name) => capabilities::ALL.contains(&name),
+        Err(_) => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn hb_register_event_callback(
+    event: HbEvent,
+    callback: HbEventCallback,
+    user_data: *mut c_void,
+) -> HbEventHandle {
+    EVENT_BUS
+        .lock()
+        .unwrap()
+        .register(event, callback, user_data)
+}
+
+#[no_mangle]
+pub extern "C" fn hb_unregister_event_callback(handle: HbEventHandle) -> bool {
+    EVENT_BUS.lock().unwrap().unregister(handle)
+}
+
+#[no_mangle]
+pub extern "C" fn hb_fire_event(event: HbEvent) {
+    match event {
+        // hyperbeam-essentials reports every frame spent in a dungeon
+        events::ENTERED_DUNGEON => update_location(Location::Dungeon),
+        _ => fire_event(event),
+    }
+}
@@ -0,0 +1,265 @@
+use crate::modpack_files;
+use hyperbeam_rtdx::archive::to_io_error;
+use hyperbeam_rtdx::modpack::is_modpack_archive;
+use ring::digest::{Context, Digest, SHA256};
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use zip::result::ZipError;
+
+/// Name of the optional file with the SHA-256 hashes of a modpack's files, next to `modpack.yaml`.
+/// It uses the format of `sha256sum`, so it can be generated with
+/// `tools/generate_checksums.sh` or checked with `sha256sum -c` on a PC.
+pub const CHECKSUMS_FILE_NAME: &str = "checksums";
+
+type Error = Box<dyn error::Error + Send + Sync>;
+
+#[derive(Debug)]
+pub struct ChecksumsParseError {
+    line_number: usize,
+}
+
+impl error::Error for ChecksumsParseError {}
+
+impl Display for ChecksumsParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid line {} in {}",
+            self.line_number, CHECKSUMS_FILE_NAME
+        )
+    }
+}
+
+/// The expected hash of a file, by its path relative to the modpack root
+#[derive(Debug, Eq, PartialEq)]
+pub struct Checksum {
+    pub path: String,
+    /// Lowercase hex SHA-256 hash
+    pub hash: String,
+}
+
+#[derive(Debug)]
+pub enum ChecksumProblem {
+    Missing(String),
+    Mismatch(String),
+    Unreadable { path: String, error: io::Error },
+}
+
+impl Display for ChecksumProblem {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ChecksumProblem::Missing(path) => write!(f, "Missing: {}", path),
+            ChecksumProblem::Mismatch(path) => write!(f, "Damaged: {}", path),
+            ChecksumProblem::Unreadable { path, error } => {
+                write!(f, "Unreadable: {} ({})", path, error)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct VerificationReport {
+    /// Number of files listed in the checksums file
+    pub file_count: usize,
+    pub problems: Vec<ChecksumProblem>,
+}
+
+/// The result of verifying a modpack, which is `None` if it doesn't have a checksums file
+pub type VerificationResult = Result<Option<VerificationReport>, Error>;
+pub type VerificationReceiver = Receiver<(PathBuf, VerificationResult)>;
+
+/// Checks that a path from the checksums file points into the modpack's `romfs` or `plugins`
+/// folder, so that verifying a modpack can't read files outside of it.
+fn is_valid_path(path: &str) -> bool {
+    match path.split_once('/') {
+        Some(("romfs", rest)) | Some(("plugins", rest)) => rest.split('/').all(|component| {
+            !matches!(component, "" | "." | "..") && !component.contains(&['\\', ':'][..])
+        }),
+        _ => false,
+    }
+}
+
+/// Parses the lines of a checksums file, which have the form `<hash>  <path>`.
+/// Empty lines are ignored. Lines with paths outside of the `romfs` and `plugins` folders are
+/// invalid.
+pub fn parse_checksums(text: &str) -> Result<Vec<Checksum>, ChecksumsParseError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let invalid = || ChecksumsParseError { line_number: i + 1 };
+            let (hash, path) = line.split_at(line.find(' ').ok_or_else(invalid)?);
+            // `sha256sum` separates the path with a space and a space or `*` for binary mode
+            let path = path
+                .strip_prefix("  ")
+                .or_else(|| path.strip_prefix(" *"))
+                .ok_or_else(invalid)?;
+            let path = path.trim_start_matches("./");
+            if hash.len() != 64
+                || !hash.chars().all(|c| c.is_ascii_hexdigit())
+                || !is_valid_path(path)
+            {
+                return Err(invalid());
+            }
+            Ok(Checksum {
+                path: path.to_owned(),
+                hash: hash.to_ascii_lowercase(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the SHA-256 digest of everything the reader returns, without reading it into memory
+/// at once.
+pub fn digest_reader<R: Read>(mut reader: R) -> io::Result<Digest> {
+    let mut context = Context::new(&SHA256);
+    let mut buffer = vec![0; 0x10000];
+    loop {
+        let count = reader.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        context.update(&buffer[..count]);
+    }
+    Ok(context.finish())
+}
+
+pub fn hash_reader<R: Read>(reader: R) -> io::Result<String> {
+    Ok(digest_reader(reader)?
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn check_file(checksum: &Checksum, hash: io::Result<String>) -> Option<ChecksumProblem> {
+    match hash {
+        Ok(hash) if hash == checksum.hash => None,
+        Ok(_) => Some(ChecksumProblem::Mismatch(checksum.path.clone())),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            Some(ChecksumProblem::Missing(checksum.path.clone()))
+        }
+        Err(error) => Some(ChecksumProblem::Unreadable {
+            path: checksum.path.clone(),
+            error,
+        }),
+    }
+}
+
+/// Hashes all files listed in the modpack's checksums file and reports the ones that are
+/// missing or differ.
+pub fn verify(modpack_path: &Path) -> VerificationResult {
+    let checksums = match modpack_files::read_file(modpack_path, CHECKSUMS_FILE_NAME)? {
+        Some(data) => parse_checksums(&String::from_utf8(data)?)?,
+        None => return Ok(None),
+    };
+
+    let mut problems = Vec::new();
+    if is_modpack_archive(modpack_path) {
+        let mut archive = modpack_files::open_archive(modpack_path)?;
+        for checksum in &checksums {
+            let hash = match archive.by_name(&checksum.path) {
+                Ok(file) => hash_reader(file),
+                Err(ZipError::FileNotFound) => Err(io::ErrorKind::NotFound.into()),
+                Err(error) => Err(to_io_error(error)),
+            };
+            problems.extend(check_file(checksum, hash));
+        }
+    } else {
+        for checksum in &checksums {
+            let hash = File::open(modpack_path.join(&checksum.path)).and_then(hash_reader);
+            problems.extend(check_file(checksum, hash));
+        }
+    }
+
+    Ok(Some(VerificationReport {
+        file_count: checksums.len(),
+        problems,
+    }))
+}
+
+/// Verifies a modpack on a separate thread. The modpack path is sent back with the result.
+pub fn start_verify(modpack_path: PathBuf) -> VerificationReceiver {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let result = verify(&modpack_path);
+        if let Err(error) = tx.send((modpack_path, result)) {
+            eprintln!("Verification thread failed to send message: {:?}", error);
+        }
+    });
+
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn parse_path(path: &str) -> Result<Vec<Checksum>, ChecksumsParseError> {
+        parse_checksums(&format!("{}  {}\n", HASH, path))
+    }
+
+    #[test]
+    fn parse_valid_lines() {
+        let text = format!(
+            "{}  romfs/Data/a.bin\n\n{} *./plugins/libplugin.nro\n",
+            HASH,
+            HASH.to_ascii_uppercase()
+        );
+        assert_eq!(
+            parse_checksums(&text).unwrap(),
+            vec![
+                Checksum {
+                    path: "romfs/Data/a.bin".to_owned(),
+                    hash: HASH.to_owned(),
+                },
+                Checksum {
+                    path: "plugins/libplugin.nro".to_owned(),
+                    hash: HASH.to_owned(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid_lines() {
+        for text in &[
+            "romfs/a.bin",
+            "abc  romfs/a.bin",
+            &format!("{} romfs/a.bin", HASH),
+            &format!("{}  ", HASH),
+        ] {
+            assert!(parse_checksums(text).is_err(), "{}", text);
+        }
+        let error = parse_checksums(&format!("{}  romfs/a.bin\ninvalid", HASH)).unwrap_err();
+        assert_eq!(error.line_number, 2);
+    }
+
+    #[test]
+    fn reject_paths_outside_modpack_folders() {
+        for path in &[
+            "modpack.yaml",
+            "romfs",
+            "romfs/",
+            "/romfs/a.bin",
+            "sd:/romfs/a.bin",
+            "../romfs/a.bin",
+            "romfs/../../a.bin",
+            "romfs/./a.bin",
+            "romfs//a.bin",
+            "romfs\\..\\..\\a.bin",
+            "plugins/..",
+            "other/a.bin",
+        ] {
+            assert!(parse_path(path).is_err(), "{}", path);
+        }
+    }
+}
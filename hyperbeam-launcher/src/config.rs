@@ -1,4 +1,6 @@
+use crate::save_backup::DEFAULT_BACKUP_COUNT;
 use lazy_static;
+use semver::Version;
 use serde::Deserialize;
 use std::error::Error;
 use std::fs;
@@ -6,10 +8,66 @@ use std::fs;
 pub static CONFIG_PATH: &str =
     "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/config.yaml";
 
+/// Which releases of hyperbeam are offered as updates
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    Stable,
+    /// Also offers releases that are marked as pre-releases
+    Prerelease,
+}
+
+impl Default for UpdateChannel {
+    fn default() -> UpdateChannel {
+        UpdateChannel::Stable
+    }
+}
+
 #[derive(Debug, Deserialize, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub auto_launch: Option<String>,
+    /// Number of save backups to keep per save slot, 0 disables backups
+    pub backup_count: Option<usize>,
+    /// Hex encoded ed25519 public keys of plugin authors. Plugins that aren't signed with one of
+    /// these keys are only loaded after confirmation.
+    #[serde(default)]
+    pub trusted_keys: Vec<String>,
+    #[serde(default)]
+    pub update_channel: UpdateChannel,
+    /// URL of the GitHub releases API of a repository or of a static update manifest, e.g. for
+    /// forks of hyperbeam
+    pub update_feed: Option<String>,
+    /// Name of the release asset that contains the launcher, `*` matches any characters. The asset
+    /// must be a zip archive with paths relative to the game's romfs folder.
+    pub update_asset_pattern: Option<String>,
+    /// Hex encoded ed25519 public keys that sign updates. With keys, unsigned updates are rejected.
+    /// Without keys, updates are verified with their SHA-256 checksum.
+    #[serde(default)]
+    pub update_keys: Vec<String>,
+    /// Version of hyperbeam that shouldn't be offered as an update. Newer versions are still
+    /// offered.
+    pub skip_version: Option<String>,
+}
+
+impl Config {
+    pub fn backup_count(&self) -> usize {
+        self.backup_count.unwrap_or(DEFAULT_BACKUP_COUNT)
+    }
+
+    pub fn skipped_version(&self) -> Option<Version> {
+        let version = self.skip_version.as_ref()?;
+        match Version::parse(version.trim_start_matches('v')) {
+            Ok(version) => Some(version),
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-launcher] Invalid skipVersion {}: {}",
+                    version, error
+                );
+                None
+            }
+        }
+    }
 }
 
 lazy_static::lazy_static! {
@@ -0,0 +1,221 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Names of the folders used by [`replace_dir_with_copy`] when replacing the contents of a mount
+const STAGING_DIR_NAME: &str = ".hyperbeam_staging";
+const PREVIOUS_DIR_NAME: &str = ".hyperbeam_previous";
+
+/// Recursively copies the contents of `from` into `to`, creating `to` if necessary.
+pub fn copy_dir_all(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn remove_dir_if_exists(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+/// Appends a suffix to the last component of a path, e.g. `saves/foo` to `saves/foo.staging`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Whether the path is the root of a mount like `save:/`, which can't be renamed
+fn is_mount_root(path: &Path) -> bool {
+    path.parent()
+        .map(|parent| parent.as_os_str().is_empty())
+        .unwrap_or(true)
+}
+
+/// Moves all entries of `from` except the staging folders into `to`.
+fn move_entries(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == OsStr::new(STAGING_DIR_NAME) || name == OsStr::new(PREVIOUS_DIR_NAME) {
+            continue;
+        }
+        fs::rename(entry.path(), to.join(name))?;
+    }
+    Ok(())
+}
+
+/// Copies `from` into a staging folder, which is removed again if copying fails.
+fn copy_to_staging(from: &Path, staging: &Path) -> io::Result<()> {
+    remove_dir_if_exists(staging)?;
+    if let Err(error) = copy_dir_all(from, staging) {
+        let _ = fs::remove_dir_all(staging);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Replaces `to` with a copy of `from`. The copy is made next to `to` first and then swapped in
+/// with renames, so `to` is left untouched if copying fails.
+///
+/// The root of a mount can't be renamed, so its new contents are staged in a folder inside it and
+/// its entries are swapped one by one instead.
+pub fn replace_dir_with_copy(from: &Path, to: &Path) -> io::Result<()> {
+    if is_mount_root(to) {
+        return replace_dir_entries_with_copy(from, to);
+    }
+
+    let staging = with_suffix(to, ".staging");
+    let previous = with_suffix(to, ".previous");
+    copy_to_staging(from, &staging)?;
+    remove_dir_if_exists(&previous)?;
+    if to.exists() {
+        if let Err(error) = fs::rename(to, &previous) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(error);
+        }
+    }
+    if let Err(error) = fs::rename(&staging, to) {
+        if previous.exists() {
+            let _ = fs::rename(&previous, to);
+        }
+        return Err(error);
+    }
+    remove_dir_if_exists(&previous)
+}
+
+fn replace_dir_entries_with_copy(from: &Path, to: &Path) -> io::Result<()> {
+    let staging = to.join(STAGING_DIR_NAME);
+    let previous = to.join(PREVIOUS_DIR_NAME);
+    copy_to_staging(from, &staging)?;
+    remove_dir_if_exists(&previous)?;
+    fs::create_dir(&previous)?;
+
+    if let Err(error) = move_entries(to, &previous) {
+        // Only previous entries were moved so far
+        move_entries(&previous, to)?;
+        fs::remove_dir_all(&staging)?;
+        fs::remove_dir(&previous)?;
+        return Err(error);
+    }
+    if let Err(error) = move_entries(&staging, to) {
+        // All previous entries were moved, so everything else in `to` is staged
+        move_entries(to, &staging)?;
+        move_entries(&previous, to)?;
+        fs::remove_dir_all(&staging)?;
+        fs::remove_dir(&previous)?;
+        return Err(error);
+    }
+    fs::remove_dir(&staging)?;
+    fs::remove_dir_all(&previous)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_file(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn read_tree(path: &Path) -> Vec<(String, String)> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path).unwrap() {
+            let entry = entry.unwrap();
+            let name = entry.file_name().into_string().unwrap();
+            if entry.path().is_dir() {
+                for (child, contents) in read_tree(&entry.path()) {
+                    files.push((format!("{}/{}", name, child), contents));
+                }
+            } else {
+                files.push((name, fs::read_to_string(entry.path()).unwrap()));
+            }
+        }
+        files.sort();
+        files
+    }
+
+    fn tree(files: &[(&str, &str)]) -> Vec<(String, String)> {
+        files
+            .iter()
+            .map(|(name, contents)| (name.to_string(), contents.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn replace_dir() {
+        let root = TempDir::new().unwrap();
+        let from = root.path().join("from");
+        let to = root.path().join("to");
+        create_file(&from.join("a"), "new a");
+        create_file(&from.join("dir/b"), "new b");
+        create_file(&to.join("a"), "old a");
+        create_file(&to.join("c"), "old c");
+
+        replace_dir_with_copy(&from, &to).unwrap();
+        assert_eq!(read_tree(&to), tree(&[("a", "new a"), ("dir/b", "new b")]));
+        assert_eq!(
+            read_tree(root.path()).len(),
+            read_tree(&from).len() + read_tree(&to).len()
+        );
+    }
+
+    #[test]
+    fn replace_missing_dir() {
+        let root = TempDir::new().unwrap();
+        let from = root.path().join("from");
+        let to = root.path().join("to");
+        create_file(&from.join("a"), "new a");
+
+        replace_dir_with_copy(&from, &to).unwrap();
+        assert_eq!(read_tree(&to), tree(&[("a", "new a")]));
+    }
+
+    #[test]
+    fn failed_copy_keeps_dir() {
+        let root = TempDir::new().unwrap();
+        let to = root.path().join("to");
+        create_file(&to.join("a"), "old a");
+
+        assert!(replace_dir_with_copy(&root.path().join("missing"), &to).is_err());
+        assert_eq!(read_tree(root.path()), tree(&[("to/a", "old a")]));
+    }
+
+    #[test]
+    fn replace_dir_entries() {
+        let root = TempDir::new().unwrap();
+        let from = root.path().join("from");
+        let to = root.path().join("to");
+        create_file(&from.join("a"), "new a");
+        create_file(&from.join("dir/b"), "new b");
+        create_file(&to.join("a"), "old a");
+        create_file(&to.join("dir/c"), "old c");
+
+        replace_dir_entries_with_copy(&from, &to).unwrap();
+        assert_eq!(read_tree(&to), tree(&[("a", "new a"), ("dir/b", "new b")]));
+
+        assert!(replace_dir_entries_with_copy(&root.path().join("missing"), &to).is_err());
+        assert_eq!(read_tree(&to), tree(&[("a", "new a"), ("dir/b", "new b")]));
+    }
+
+    #[test]
+    fn mount_roots() {
+        assert!(is_mount_root(Path::new("save:/")));
+        assert!(!is_mount_root(Path::new(
+            "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/saves/foo"
+        )));
+    }
+}
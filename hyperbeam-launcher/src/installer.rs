@@ -0,0 +1,320 @@
+use crate::fs_helpers::remove_dir_if_exists;
+use crate::modpack::{self, Modpack};
+use hyperbeam_rtdx::modpack::{MODPACK_ARCHIVE_EXTENSION, MODPACK_BASE_PATH};
+use serde::Deserialize;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use zip::ZipArchive;
+
+/// Modpack archives placed here are installed on the next start
+pub const INBOX_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/inbox";
+/// Archives that failed to install are moved here, so that they aren't retried on every start
+const FAILED_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/inbox/failed";
+/// Modpacks are extracted here before they're moved to the modpack folder
+const STAGING_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/inbox/.staging";
+/// Files in an installed modpack that belong to the user and are kept when it's upgraded
+const PRESERVED_FILES: &[&str] = &["config.yaml"];
+
+type Error = Box<dyn error::Error + Send + Sync>;
+
+pub enum InstallProgress {
+    Extracting {
+        archive_name: String,
+        extracted: usize,
+        total: usize,
+    },
+    Installed {
+        archive_name: String,
+        id: String,
+    },
+    Failed {
+        archive_name: String,
+        error: Error,
+    },
+    Finished,
+}
+
+pub type InstallReceiver = Receiver<InstallProgress>;
+
+#[derive(Debug)]
+struct MissingManifestError;
+
+impl error::Error for MissingManifestError {}
+
+impl Display for MissingManifestError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "modpack.yaml not found in archive")
+    }
+}
+
+#[derive(Debug)]
+struct InvalidIDError(String);
+
+impl error::Error for InvalidIDError {}
+
+impl Display for InvalidIDError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Invalid modpack ID \"{}\"", self.0)
+    }
+}
+
+#[derive(Debug)]
+struct InvalidModpackError(String);
+
+impl error::Error for InvalidModpackError {}
+
+impl Display for InvalidModpackError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Deserialize)]
+struct ManifestID {
+    id: String,
+}
+
+/// Returns the zip and modpack archives in the inbox folder.
+pub fn pending_archives() -> Vec<PathBuf> {
+    let mut archives: Vec<PathBuf> = match fs::read_dir(INBOX_PATH) {
+        Ok(dir_contents) => dir_contents
+            .filter_map(|f| f.ok())
+            .map(|f| f.path())
+            .filter(|f| f.is_file())
+            .filter(|f| {
+                f.extension()
+                    .and_then(|extension| extension.to_str())
+                    .map(|extension| {
+                        extension.eq_ignore_ascii_case("zip")
+                            || extension.eq_ignore_ascii_case(MODPACK_ARCHIVE_EXTENSION)
+                    })
+                    .unwrap_or(false)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    archives.sort();
+    archives
+}
+
+/// Finds the folder in the archive that contains `modpack.yaml`, which is either the root or
+/// a single folder that the modpack was zipped with. Returns the prefix of the entries in it.
+fn find_modpack_root(archive: &ZipArchive<File>) -> Option<String> {
+    archive
+        .file_names()
+        .filter_map(|name| name.strip_suffix("modpack.yaml"))
+        .filter(|prefix| prefix.is_empty() || prefix.ends_with('/'))
+        .min_by_key(|prefix| prefix.matches('/').count())
+        .map(str::to_owned)
+}
+
+fn read_id(archive: &mut ZipArchive<File>, root: &str) -> Result<String, Error> {
+    let manifest = archive.by_name(&format!("{}modpack.yaml", root))?;
+    let manifest: ManifestID = serde_yaml::from_reader(manifest)?;
+
+    // The ID is used as folder name, so it must not point anywhere else
+    let mut components = Path::new(&manifest.id).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(manifest.id),
+        _ => Err(Box::new(InvalidIDError(manifest.id))),
+    }
+}
+
+/// Extracts the modpack in the archive to `destination`, reporting progress after every file.
+fn extract<F: FnMut(usize, usize)>(
+    archive: &mut ZipArchive<File>,
+    root: &str,
+    destination: &Path,
+    mut progress: F,
+) -> Result<(), Error> {
+    let total = archive.len();
+    for i in 0..total {
+        let mut file = archive.by_index(i)?;
+        let relative_path = match file
+            .enclosed_name()
+            .and_then(|path| path.strip_prefix(root).ok())
+        {
+            Some(path) if path.components().next().is_some() => path.to_owned(),
+            _ => continue,
+        };
+
+        let out_path = destination.join(relative_path);
+        if file.is_dir() {
+            fs::create_dir_all(&out_path)?;
+        } else {
+            if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&out_path)?)?;
+        }
+        progress(i + 1, total);
+    }
+    Ok(())
+}
+
+/// Replaces the installed modpack with the extracted one. If anything fails, the installed
+/// modpack is restored.
+fn replace_installed(
+    extracted_path: &Path,
+    install_path: &Path,
+    backup_path: &Path,
+) -> io::Result<()> {
+    remove_dir_if_exists(backup_path)?;
+    if !install_path.exists() {
+        return fs::rename(extracted_path, install_path);
+    }
+
+    for file_name in PRESERVED_FILES {
+        let preserved_file = install_path.join(file_name);
+        if preserved_file.is_file() {
+            fs::copy(&preserved_file, extracted_path.join(file_name))?;
+        }
+    }
+
+    fs::rename(install_path, backup_path)?;
+    if let Err(error) = fs::rename(extracted_path, install_path) {
+        fs::rename(backup_path, install_path)?;
+        return Err(error);
+    }
+    if let Err(error) = fs::remove_dir_all(backup_path) {
+        eprintln!(
+            "[hyperbeam-launcher] Failed to remove the previous version of {}: {}",
+            install_path.display(),
+            error
+        );
+    }
+    Ok(())
+}
+
+/// Installs a single modpack archive into the modpack folder and returns the modpack's ID.
+/// The archive is validated like an installed modpack before anything is replaced.
+fn install_archive<F: FnMut(usize, usize)>(
+    archive_path: &Path,
+    progress: F,
+) -> Result<String, Error> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let root = find_modpack_root(&archive).ok_or(MissingManifestError)?;
+    let id = read_id(&mut archive, &root)?;
+
+    // The extracted folder must be named like the modpack for validation
+    let extracted_path = Path::new(STAGING_PATH).join(&id);
+    let backup_path = Path::new(STAGING_PATH).join(format!("{}.previous", id));
+    remove_dir_if_exists(&extracted_path)?;
+
+    let result = extract(&mut archive, &root, &extracted_path, progress)
+        .and_then(|_| match Modpack::new(&extracted_path) {
+            Ok(_) => Ok(()),
+            Err(problems) => Err(Box::new(InvalidModpackError(
+                problems
+                    .iter()
+                    .filter(|problem| problem.severity() == modpack::Severity::Error)
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )) as Error),
+        })
+        .and_then(|_| {
+            let install_path = Path::new(MODPACK_BASE_PATH).join(&id);
+            Ok(replace_installed(
+                &extracted_path,
+                &install_path,
+                &backup_path,
+            )?)
+        });
+
+    if let Err(error) = remove_dir_if_exists(&extracted_path) {
+        eprintln!(
+            "[hyperbeam-launcher] Failed to clean up {}: {}",
+            extracted_path.display(),
+            error
+        );
+    }
+    result.map(|_| id)
+}
+
+fn move_to_failed(archive_path: &Path) -> io::Result<()> {
+    fs::create_dir_all(FAILED_PATH)?;
+    let file_name = archive_path.file_name().unwrap_or_default();
+    fs::rename(archive_path, Path::new(FAILED_PATH).join(file_name))
+}
+
+/// Installs all archives in the inbox. Installed archives are deleted, archives that failed to
+/// install are moved to the `failed` folder in the inbox.
+pub fn install_inbox<F: FnMut(InstallProgress)>(mut progress: F) {
+    for archive_path in pending_archives() {
+        let archive_name = archive_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+            .to_owned();
+        println!("[hyperbeam-launcher] Installing {}...", archive_name);
+
+        let result = install_archive(&archive_path, |extracted, total| {
+            progress(InstallProgress::Extracting {
+                archive_name: archive_name.clone(),
+                extracted,
+                total,
+            })
+        });
+        match result {
+            Ok(id) => {
+                println!(
+                    "[hyperbeam-launcher] Installed modpack {} from {}",
+                    id, archive_name
+                );
+                if let Err(error) = fs::remove_file(&archive_path) {
+                    eprintln!(
+                        "[hyperbeam-launcher] Failed to remove installed archive {}: {}",
+                        archive_name, error
+                    );
+                }
+                progress(InstallProgress::Installed { archive_name, id });
+            }
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to install {}: {}",
+                    archive_name, error
+                );
+                if let Err(error) = move_to_failed(&archive_path) {
+                    eprintln!(
+                        "[hyperbeam-launcher] Failed to move {} out of the inbox: {}",
+                        archive_name, error
+                    );
+                }
+                progress(InstallProgress::Failed {
+                    archive_name,
+                    error,
+                });
+            }
+        }
+    }
+
+    if let Err(error) = remove_dir_if_exists(Path::new(STAGING_PATH)) {
+        eprintln!(
+            "[hyperbeam-launcher] Failed to clean up staging folder: {}",
+            error
+        );
+    }
+    progress(InstallProgress::Finished);
+}
+
+/// Installs all archives in the inbox on a separate thread.
+pub fn start_install_inbox() -> InstallReceiver {
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        install_inbox(|progress| {
+            if let Err(error) = tx.send(progress) {
+                eprintln!("Install thread failed to send message: {:?}", error);
+            }
+        });
+    });
+
+    rx
+}
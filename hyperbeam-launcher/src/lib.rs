@@ -1,37 +1,92 @@
 #![feature(proc_macro_hygiene)]
 #![feature(asm)]
 
+mod api;
+mod checksums;
 mod config;
+mod fs_helpers;
+mod installer;
+mod load_order;
 mod modpack;
+mod modpack_config;
+mod modpack_files;
+mod plugin_signature;
+mod save_backup;
+mod save_data;
 mod self_update;
+mod update_installer;
 
-use crate::self_update::UpdateCheckResult;
+use crate::checksums::{VerificationReceiver, VerificationResult};
+use crate::installer::{InstallProgress, InstallReceiver};
+use crate::self_update::{
+    DownloadProgress, Update, UpdateCheckResult, UpdateProgress, UpdateSettings,
+};
+use hyperbeam_api::events::{self, Location};
 use hyperbeam_rtdx::input;
-use hyperbeam_rtdx::modpack::ModpackMetadata;
+use hyperbeam_rtdx::modpack::{CurrentModpack, ModpackLayer};
 use hyperbeam_unity::{reflect, texture_helpers, IlString};
 use image;
-use modpack::{Modpack, ModpackLoadResult};
+use modpack::{
+    InvalidModpack, Modpack, ModpackError, ModpackLoadResult, PluginFailure, UntrustedPlugin,
+};
+use modpack_config::ModpackConfig;
 use pmdrtdx_bindings::*;
-use self_update::UpdateCheckReceiver;
+use save_backup::{BackupManager, SaveBackup};
+use self_update::{UpdateCheckReceiver, UpdateReceiver};
 use skyline::nn;
 use skyline::{hook, install_hook, install_hooks};
 use std::cmp::{Eq, PartialEq};
+use std::error::Error;
 use std::ffi::CString;
+use std::fmt::Display;
 use std::mem;
 use std::os::raw::c_char;
-use std::ptr::null_mut;
+use std::path::PathBuf;
+use std::ptr::{null_mut, NonNull};
 use std::string::String;
+use std::sync::mpsc::TryRecvError;
 
 #[derive(Debug)]
 enum State {
     Initializing,
+    Installing(InstallReceiver),
     UpdateCheck(UpdateCheckReceiver),
     ModpackSelect,
+    ConfirmSaveTransfer(SaveTransfer),
+    SaveBackups {
+        backups: Vec<SaveBackup>,
+        selection: usize,
+        confirm_restore: bool,
+    },
+    ModpackDetails {
+        screenshots: Vec<String>,
+        screenshot_index: usize,
+        screenshot: Option<NonNull<Texture2D>>,
+        splash_image_was_active: bool,
+    },
+    PluginSettings {
+        /// Names of the modpack's optional plugins
+        plugins: Vec<String>,
+        config: ModpackConfig,
+        selection: usize,
+    },
+    Message,
+    ConfirmUntrustedPlugins,
+    UpdateAvailable,
+    Updating(UpdateReceiver),
+    UpdateFailed,
     PreLoadingAnimation,
+    ConfirmPluginFailures,
     Loading,
     Loaded,
 }
 
+#[derive(Debug, Copy, Clone)]
+enum SaveTransfer {
+    VanillaToModpack,
+    ModpackToVanilla,
+}
+
 impl PartialEq for State {
     fn eq(&self, other: &Self) -> bool {
         mem::discriminant(self) == mem::discriminant(other)
@@ -39,7 +94,7 @@ impl PartialEq for State {
 }
 impl Eq for State {}
 
-struct Globals<'a> {
+struct Globals {
     state: State,
     native_plugin_manager: *mut NativePluginManager,
     script_data_manager: *mut ScriptDataManager,
@@ -47,6 +102,9 @@ struct Globals<'a> {
     launcher_ui: *mut GameObject,
     main_container: *mut GameObject,
     pending_operation_bg: *mut GameObject,
+    pending_operation_text: *mut TMP_Text,
+    error_overlay: *mut GameObject,
+    error_text: *mut TMP_Text,
     launcher_animation: *mut Animation,
     title_text: *mut TMP_Text,
     version_bg: *mut GameObject,
@@ -54,7 +112,17 @@ struct Globals<'a> {
     vanilla_icon: *mut Texture2D,
     splash_image: *mut Texture2D,
     modpacks: Vec<ModpackLoadResult>,
-    loaded_modpack: Option<&'a Modpack>,
+    loaded_modpack: Option<CurrentModpack>,
+    available_update: Option<Update>,
+    /// Seconds since the update check started or the update reported progress. `None` while the
+    /// update is installed, which can't be abandoned.
+    update_timer: Option<f32>,
+    /// Archives from the inbox that couldn't be installed, with the reason
+    install_failures: Vec<String>,
+    /// Checksum verification running in the background
+    verification: Option<VerificationReceiver>,
+    /// Finished checksum verifications by modpack path
+    verification_results: Vec<(PathBuf, VerificationResult)>,
     icons: [(*mut GameObject, *mut RawImage); 7],
     selection_index: i32,
 }
@@ -67,6 +135,9 @@ static mut GLOBALS: Globals = Globals {
     launcher_ui: null_mut(),
     main_container: null_mut(),
     pending_operation_bg: null_mut(),
+    pending_operation_text: null_mut(),
+    error_overlay: null_mut(),
+    error_text: null_mut(),
     launcher_animation: null_mut(),
     title_text: null_mut(),
     version_bg: null_mut(),
@@ -75,6 +146,11 @@ static mut GLOBALS: Globals = Globals {
     splash_image: null_mut(),
     modpacks: Vec::new(),
     loaded_modpack: None,
+    available_update: None,
+    update_timer: None,
+    install_failures: Vec::new(),
+    verification: None,
+    verification_results: Vec::new(),
     selection_index: 0,
     icons: [(null_mut(), null_mut()); 7],
 };
@@ -119,6 +195,14 @@ unsafe fn init_launcher_ui() {
     );
     GLOBALS.pending_operation_bg = Component_1_get_gameObject(pending_op_text as _, null_mut());
 
+    let error_overlay = Transform_Find(
+        transform,
+        IlString::new("ErrorOverlay").as_ptr(),
+        null_mut(),
+    );
+    GLOBALS.error_overlay = Component_1_get_gameObject(error_overlay as _, null_mut());
+    GameObject_SetActive(GLOBALS.error_overlay, false, null_mut());
+
     GLOBALS.launcher_animation =
         GameObject_GetComponent(GLOBALS.launcher_ui as _, animation_type as _, null_mut())
             as *mut Animation;
@@ -150,11 +234,125 @@ unsafe fn init_launcher_ui() {
     shader_pack_wrapper.unload(false);
 }
 
-unsafe fn start_update_check() {
+unsafe fn show_pending_operation<T: AsRef<str>>(text: T) {
+    TMP_Text_set_text(
+        GLOBALS.pending_operation_text,
+        IlString::new(text).as_ptr(),
+        null_mut(),
+    );
     GameObject_SetActive(GLOBALS.main_container, false, null_mut());
     GameObject_SetActive(GLOBALS.pending_operation_bg, true, null_mut());
+}
 
-    GLOBALS.state = State::UpdateCheck(self_update::start_check_self_update());
+unsafe fn start_install() {
+    show_pending_operation("Installing modpacks...");
+    GLOBALS.state = State::Installing(installer::start_install_inbox());
+}
+
+unsafe fn update_install_progress(receiver: &InstallReceiver) {
+    // Every extracted file is reported, so handle everything that arrived since the last frame
+    while let Ok(progress) = receiver.try_recv() {
+        match progress {
+            InstallProgress::Extracting {
+                archive_name,
+                extracted,
+                total,
+            } => show_pending_operation(format!(
+                "Installing {}...\n{}/{} files",
+                archive_name, extracted, total
+            )),
+            InstallProgress::Installed { .. } => {}
+            InstallProgress::Failed {
+                archive_name,
+                error,
+            } => GLOBALS
+                .install_failures
+                .push(format!("{}: {}", archive_name, error)),
+            InstallProgress::Finished => {
+                reload_modpacks();
+                start_update_check();
+                return;
+            }
+        }
+    }
+}
+
+/// Reloads the modpack list after modpacks were installed.
+unsafe fn reload_modpacks() {
+    for load_result in &mut GLOBALS.modpacks {
+        if let ModpackLoadResult::Success(modpack) = load_result {
+            modpack.unload_icon();
+        }
+    }
+    match modpack::load_all_modpacks() {
+        Ok(modpacks) => GLOBALS.modpacks = modpacks,
+        Err(error) => eprintln!("[hyperbeam-launcher] Failed to reload modpacks: {}", error),
+    }
+    GLOBALS.selection_index = GLOBALS.selection_index.min(GLOBALS.modpacks.len() as i32);
+    show_selected_modpack();
+}
+
+/// Shows the archives that couldn't be installed once the modpack selection is shown.
+unsafe fn show_install_failures() {
+    if !GLOBALS.install_failures.is_empty() {
+        show_message(format!(
+            "Failed to install modpacks:\n\n{}",
+            GLOBALS.install_failures.join("\n")
+        ));
+        GLOBALS.install_failures.clear();
+    }
+}
+
+unsafe fn start_update_check() {
+    show_pending_operation("Checking for updates...");
+
+    let settings = UpdateSettings::from_config(config::get_config());
+    GLOBALS.update_timer = Some(0.0);
+    GLOBALS.state = State::UpdateCheck(self_update::start_check_self_update(settings));
+}
+
+unsafe fn advance_update_timer(dt: f32) {
+    if let Some(timer) = &mut GLOBALS.update_timer {
+        *timer += dt;
+    }
+}
+
+unsafe fn update_timed_out(timeout: f32) -> bool {
+    GLOBALS.update_timer.map_or(false, |timer| timer > timeout)
+}
+
+/// Shows the modpack selection once the update check finished, failed or timed out. Offers the
+/// available update, unless modpacks failed to install.
+unsafe fn finish_update_check() {
+    GLOBALS.state = State::ModpackSelect;
+    GameObject_SetActive(GLOBALS.pending_operation_bg, false, null_mut());
+    GameObject_SetActive(GLOBALS.main_container, true, null_mut());
+    update_installer::confirm_update();
+    if !GLOBALS.install_failures.is_empty() {
+        show_install_failures();
+    } else if let Some(update) = &GLOBALS.available_update {
+        show_update_available(update, "A new version of hyperbeam is available.");
+    }
+}
+
+unsafe fn update_update_check(receiver: &UpdateCheckReceiver, dt: f32) {
+    advance_update_timer(dt);
+    match receiver.try_recv() {
+        Ok(Ok(UpdateCheckResult::UpdateAvailable(update))) => {
+            GLOBALS.available_update = Some(update);
+            finish_update_check();
+        }
+        Ok(Ok(UpdateCheckResult::NoUpdate)) => finish_update_check(),
+        Ok(Err(error)) => {
+            eprintln!("[hyperbeam-launcher] Update check failed: {}", error);
+            finish_update_check();
+        }
+        Err(_) if update_timed_out(UPDATE_CHECK_TIMEOUT) => {
+            eprintln!("[hyperbeam-launcher] Update check timed out.");
+            finish_update_check();
+        }
+        Err(_) => {}
+    }
 }
 
 unsafe fn find_and_fix_text_meshes(root: *mut Transform, font: *mut TMP_FontAsset) {
@@ -183,6 +381,7 @@ unsafe fn find_and_fix_text_meshes(root: *mut Transform, font: *mut TMP_FontAsse
     let text = find_text(root, "BackgroundOverlay/PendingOperationText", tmp_type);
     TMP_Text_set_font(text, font, null_mut());
     TMP_Text_set_alignment(text, TextAlignmentOptions__Enum_Center, null_mut());
+    GLOBALS.pending_operation_text = text;
 
     let text = find_text(root, "MainUIContainer/Footer/Layout/SelectText", tmp_type);
     TMP_Text_set_font(text, font, null_mut());
@@ -199,6 +398,8 @@ unsafe fn find_and_fix_text_meshes(root: *mut Transform, font: *mut TMP_FontAsse
     let text = find_text(root, "ErrorOverlay/ErrorOverlayInner/ErrorText", tmp_type);
     TMP_Text_set_font(text, font, null_mut());
     TMP_Text_set_alignment(text, TextAlignmentOptions__Enum_Center, null_mut());
+    TMP_Text_set_enableWordWrapping(text, true, null_mut());
+    GLOBALS.error_text = text;
 }
 
 unsafe fn find_text(
@@ -210,25 +411,394 @@ unsafe fn find_text(
     Component_1_GetComponent(transform as _, text_mesh_pro_type as _, null_mut()) as *mut TMP_Text
 }
 
-unsafe fn show_splash_image() {
-    let modpack = get_current_modpack();
-    if modpack.is_none() {
+unsafe fn show_overlay<T: AsRef<str>>(text: T) {
+    TMP_Text_set_text(GLOBALS.error_text, IlString::new(text).as_ptr(), null_mut());
+    GameObject_SetActive(GLOBALS.error_overlay, true, null_mut());
+}
+
+unsafe fn hide_overlay() {
+    GameObject_SetActive(GLOBALS.error_overlay, false, null_mut());
+}
+
+unsafe fn show_message<T: AsRef<str>>(text: T) {
+    show_overlay(format!("{}\n\nB: Close", text.as_ref()));
+    GLOBALS.state = State::Message;
+}
+
+unsafe fn confirm_save_transfer(modpack: &Modpack, transfer: SaveTransfer) {
+    let text = match transfer {
+        SaveTransfer::VanillaToModpack => format!(
+            "Copy the base game's save data to \"{}\"?\nThe modpack's save data will be overwritten.",
+            &modpack.metadata.name
+        ),
+        SaveTransfer::ModpackToVanilla => format!(
+            "Copy the save data of \"{}\" to the base game?\nThe base game's save data will be overwritten.",
+            &modpack.metadata.name
+        ),
+    };
+    show_overlay(format!("{}\n\nA: Confirm    B: Cancel", text));
+    GLOBALS.state = State::ConfirmSaveTransfer(transfer);
+}
+
+unsafe fn transfer_save_data(modpack: &Modpack, transfer: SaveTransfer) {
+    let result = match transfer {
+        SaveTransfer::VanillaToModpack => {
+            save_data::copy_vanilla_save_to_modpack(&modpack.metadata.id)
+        }
+        SaveTransfer::ModpackToVanilla => {
+            save_data::copy_modpack_save_to_vanilla(&modpack.metadata.id)
+        }
+    };
+    match result {
+        Ok(_) => show_message("Save data copied."),
+        Err(error) => {
+            eprintln!("[hyperbeam-launcher] Failed to copy save data: {}", error);
+            show_message(format!("Failed to copy save data:\n{}", error));
+        }
+    }
+}
+
+/// Backups are kept separately for the base game's save data and every modpack with its own save data
+fn backup_manager(modpack: Option<&Modpack>) -> BackupManager {
+    let slot = match modpack {
+        Some(modpack) if !modpack.metadata.shared_save => modpack.metadata.id.as_str(),
+        _ => "vanilla",
+    };
+    BackupManager::for_save_slot(slot, config::get_config().backup_count())
+}
+
+fn save_path(modpack: Option<&Modpack>) -> PathBuf {
+    save_data::save_path_for(modpack.map(|modpack| &modpack.metadata))
+}
+
+fn backup_save_data(modpack: Option<&Modpack>) {
+    let save_path = save_path(modpack);
+    if !save_path.is_dir() {
+        // Modpacks don't have save data before they were launched for the first time
         return;
     }
+    match backup_manager(modpack).create_now(&save_path) {
+        Ok(Some(backup)) => println!(
+            "[hyperbeam-launcher] Backed up save data to {}",
+            backup.path.display()
+        ),
+        Ok(None) => {}
+        Err(error) => eprintln!(
+            "[hyperbeam-launcher] Failed to back up save data: {}",
+            error
+        ),
+    }
+}
 
-    let modpack = modpack.unwrap();
+const VISIBLE_SAVE_BACKUPS: usize = 8;
+
+unsafe fn show_save_backups(backups: &[SaveBackup], selection: usize, confirm_restore: bool) {
+    let text = if backups.is_empty() {
+        "No save backups yet.\n\nB: Close".to_owned()
+    } else if confirm_restore {
+        format!(
+            "Restore the backup from {}?\nThe current save data will be overwritten.\n\nA: Confirm    B: Cancel",
+            backups[selection].display_name()
+        )
+    } else {
+        let first_visible = (selection + 1).saturating_sub(VISIBLE_SAVE_BACKUPS);
+        let lines: Vec<String> = backups
+            .iter()
+            .enumerate()
+            .skip(first_visible)
+            .take(VISIBLE_SAVE_BACKUPS)
+            .map(|(i, backup)| {
+                let marker = if i == selection { "> " } else { "" };
+                format!("{}{}", marker, backup.display_name())
+            })
+            .collect();
+        format!(
+            "Save backups\n\n{}\n\nA: Restore    B: Close",
+            lines.join("\n")
+        )
+    };
+    show_overlay(text);
+}
+
+unsafe fn open_save_backups(modpack: Option<&Modpack>) {
+    let backups = match backup_manager(modpack).list() {
+        Ok(backups) => backups,
+        Err(error) => {
+            show_message(format!("Failed to list save backups:\n{}", error));
+            return;
+        }
+    };
+    show_save_backups(&backups, 0, false);
+    GLOBALS.state = State::SaveBackups {
+        backups,
+        selection: 0,
+        confirm_restore: false,
+    };
+}
+
+unsafe fn update_save_backups() {
+    let modpack = get_current_modpack();
+    let mut restore_result = None;
+
+    if let State::SaveBackups {
+        backups,
+        selection,
+        confirm_restore,
+    } = &mut GLOBALS.state
+    {
+        if *confirm_restore {
+            if input::get_button_down(input::Button::A) {
+                let save_path = save_path(modpack);
+                restore_result = Some(
+                    backup_manager(modpack)
+                        .restore(&backups[*selection], &save_path)
+                        .and_then(|_| {
+                            if save_path == PathBuf::from(save_data::SAVE_MOUNT_PATH) {
+                                save_data::commit_save_data()
+                            } else {
+                                Ok(())
+                            }
+                        }),
+                );
+            } else if input::get_button_down(input::Button::B) {
+                *confirm_restore = false;
+            }
+        } else if input::get_button_down(input::Button::B) {
+            hide_overlay();
+            GLOBALS.state = State::ModpackSelect;
+            return;
+        } else if input::get_button_down(input::Button::Up) && *selection > 0 {
+            *selection -= 1;
+        } else if input::get_button_down(input::Button::Down) && *selection + 1 < backups.len() {
+            *selection += 1;
+        } else if input::get_button_down(input::Button::A) && !backups.is_empty() {
+            *confirm_restore = true;
+        }
+
+        if restore_result.is_none() {
+            show_save_backups(backups, *selection, *confirm_restore);
+        }
+    }
+
+    match restore_result {
+        Some(Ok(_)) => show_message("Save backup restored."),
+        Some(Err(error)) => {
+            eprintln!(
+                "[hyperbeam-launcher] Failed to restore save backup: {}",
+                error
+            );
+            show_message(format!("Failed to restore save backup:\n{}", error));
+        }
+        None => {}
+    }
+}
+
+/// Shows the path and all problems of a broken modpack. If it only needs a newer version of
+/// hyperbeam and a matching update is available, the update is offered.
+unsafe fn show_broken_modpack(invalid_modpack: &InvalidModpack) {
+    let mut text = format!("Broken modpack\n{}\n", invalid_modpack.path.display());
+    for problem in &invalid_modpack.problems {
+        text.push_str(&format!("\n{}: {}", problem.severity(), problem));
+    }
+
+    let required_version = invalid_modpack
+        .problems
+        .iter()
+        .find_map(|problem| match problem {
+            ModpackError::UnsupportedLauncherVersion { required, .. } => Some(required),
+            _ => None,
+        });
+    match (required_version, &GLOBALS.available_update) {
+        (Some(required), Some(update)) if required.matches(&update.version) => {
+            show_update_available(
+                update,
+                format!(
+                    "{}\n\nhyperbeam {} supports this modpack.",
+                    text, update.version
+                ),
+            );
+        }
+        (Some(_), _) => show_message(format!(
+            "{}\n\nNo compatible update of hyperbeam is available.",
+            text
+        )),
+        (None, _) => show_message(text),
+    }
+}
+
+/// Seconds to wait for the update check before showing the modpack selection
+const UPDATE_CHECK_TIMEOUT: f32 = 20.0;
+/// Seconds without progress after which an update is considered failed
+const UPDATE_PROGRESS_TIMEOUT: f32 = 60.0;
+/// Maximum number of lines of release notes shown before updating
+const MAX_RELEASE_NOTES_LINES: usize = 12;
+/// Number of characters of the download progress bar
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+fn megabytes(bytes: u64) -> f32 {
+    bytes as f32 / (1024.0 * 1024.0)
+}
+
+/// Returns the version, size and release notes of an update.
+fn update_description(update: &Update) -> String {
+    let mut text = format!("hyperbeam {}", update.version);
+    if update.size > 0 {
+        text.push_str(&format!(" ({:.1} MB)", megabytes(update.size)));
+    }
+    let notes: Vec<&str> = update.notes.trim().lines().collect();
+    if !notes.is_empty() {
+        text.push_str("\n");
+        for line in notes.iter().take(MAX_RELEASE_NOTES_LINES) {
+            text.push_str(&format!("\n{}", line));
+        }
+        if notes.len() > MAX_RELEASE_NOTES_LINES {
+            text.push_str("\n...");
+        }
+    }
+    text
+}
+
+fn progress_bar(fraction: f32) -> String {
+    let filled = ((fraction * PROGRESS_BAR_WIDTH as f32).round() as usize).min(PROGRESS_BAR_WIDTH);
+    format!(
+        "[{}{}]",
+        "=".repeat(filled),
+        "-".repeat(PROGRESS_BAR_WIDTH - filled)
+    )
+}
+
+fn download_progress_text(progress: &DownloadProgress) -> String {
+    match (progress.fraction(), progress.total) {
+        (Some(fraction), Some(total)) => format!(
+            "Downloading update...\n\n{} {:.0}%\n{:.1} / {:.1} MB",
+            progress_bar(fraction),
+            fraction * 100.0,
+            megabytes(progress.downloaded),
+            megabytes(total)
+        ),
+        _ => format!(
+            "Downloading update...\n\n{:.1} MB",
+            megabytes(progress.downloaded)
+        ),
+    }
+}
+
+/// Offers an update, with `reason` shown above the version and release notes.
+unsafe fn show_update_available<T: AsRef<str>>(update: &Update, reason: T) {
+    show_overlay(format!(
+        "{}\n\n{}\n\nA: Install now  X: Skip this version  B: Later",
+        reason.as_ref(),
+        update_description(update)
+    ));
+    GLOBALS.state = State::UpdateAvailable;
+}
+
+unsafe fn skip_update() {
+    if let Some(update) = GLOBALS.available_update.take() {
+        println!("[hyperbeam-launcher] Skipping version {}.", update.version);
+        if let Err(error) = self_update::skip_version(&update.version) {
+            eprintln!("[hyperbeam-launcher] Failed to skip the update: {}", error);
+        }
+    }
+    hide_overlay();
+    GLOBALS.state = State::ModpackSelect;
+}
+
+unsafe fn start_self_update() {
+    // The update is kept so that it can be retried if it fails
+    if let Some(update) = &GLOBALS.available_update {
+        println!(
+            "[hyperbeam-launcher] Updating to version {}...",
+            update.version
+        );
+        show_overlay("Downloading update...");
+        let settings = UpdateSettings::from_config(config::get_config());
+        GLOBALS.update_timer = Some(0.0);
+        GLOBALS.state = State::Updating(update.clone().start_update(&settings));
+    }
+}
+
+unsafe fn show_update_failed<T: Display>(error: T) {
+    eprintln!("[hyperbeam-launcher] Failed to update: {}", error);
+    show_overlay(format!(
+        "Failed to update hyperbeam:\n{}\n\nA: Retry  B: Back",
+        error
+    ));
+    GLOBALS.state = State::UpdateFailed;
+}
+
+unsafe fn update_self_update_progress(receiver: &UpdateReceiver, dt: f32) {
+    advance_update_timer(dt);
+    // Progress is reported more often than once per frame, so only the latest is shown
+    let mut download_progress = None;
+    loop {
+        match receiver.try_recv() {
+            Ok(UpdateProgress::Downloading(progress)) => download_progress = Some(progress),
+            Ok(UpdateProgress::Installing) => {
+                GLOBALS.update_timer = None;
+                show_overlay("Installing update...");
+                return;
+            }
+            Ok(UpdateProgress::Finished) => {
+                let version = GLOBALS
+                    .available_update
+                    .take()
+                    .map(|update| update.version.to_string())
+                    .unwrap_or_default();
+                show_message(format!(
+                    "hyperbeam {} was installed.\nRestart the game to use the new version.",
+                    version
+                ));
+                return;
+            }
+            Ok(UpdateProgress::Err(error)) => {
+                show_update_failed(error);
+                return;
+            }
+            Err(TryRecvError::Disconnected) => {
+                show_update_failed("The update stopped unexpectedly.");
+                return;
+            }
+            Err(TryRecvError::Empty) => break,
+        }
+    }
+    if let Some(progress) = download_progress {
+        GLOBALS.update_timer = Some(0.0);
+        show_overlay(download_progress_text(&progress));
+    } else if update_timed_out(UPDATE_PROGRESS_TIMEOUT) {
+        // The download is resumed when retrying, unless the attempt that timed out is still
+        // writing to it
+        show_update_failed("The download timed out.");
+    }
+}
+
+unsafe fn find_splash_image() -> Option<*mut RawImage> {
     let raw_image_type = reflect::get_unity_ui_type(Some("UnityEngine.UI"), "RawImage").unwrap();
 
     let transform = GameObject_get_transform(GLOBALS.launcher_ui, null_mut());
     let splash_image_transform =
         Transform_Find(transform, IlString::new("SplashImage").as_ptr(), null_mut());
     if splash_image_transform.is_null() {
-        return;
+        return None;
     }
 
-    let splash_image_component =
+    Some(
         Component_1_GetComponent(splash_image_transform as _, raw_image_type as _, null_mut())
-            as *mut RawImage;
+            as *mut RawImage,
+    )
+}
+
+unsafe fn show_splash_image() {
+    let modpack = get_current_modpack();
+    if modpack.is_none() {
+        return;
+    }
+
+    let modpack = modpack.unwrap();
+    let splash_image_component = match find_splash_image() {
+        Some(component) => component,
+        None => return,
+    };
     match modpack.load_splash_image() {
         Ok(splash_image) => {
             RawImage_set_texture(
@@ -245,6 +815,341 @@ unsafe fn show_splash_image() {
     }
 }
 
+/// Hides the splash image shown by the launch animation, e.g. when the launch failed
+unsafe fn hide_splash_image() {
+    if let Some(splash_image) = splash_image_object() {
+        GameObject_SetActive(splash_image, false, null_mut());
+    }
+    if !GLOBALS.splash_image.is_null() {
+        Object_1_Destroy_1(GLOBALS.splash_image as _, null_mut());
+        GLOBALS.splash_image = null_mut();
+    }
+}
+
+/// Maximum number of damaged files listed in the modpack details
+const MAX_LISTED_CHECKSUM_PROBLEMS: usize = 5;
+
+fn verification_text(modpack: &Modpack) -> Option<String> {
+    let result = unsafe {
+        GLOBALS
+            .verification_results
+            .iter()
+            .find(|(path, _)| path == &modpack.path)
+            .map(|(_, result)| result)
+    };
+    match result {
+        None => Some("Verifying files...".to_owned()),
+        Some(Ok(None)) => None,
+        Some(Ok(Some(report))) if report.problems.is_empty() => {
+            Some(format!("All {} files verified.", report.file_count))
+        }
+        Some(Ok(Some(report))) => {
+            let mut text = format!(
+                "{} of {} files are missing or damaged. Copy the modpack again.",
+                report.problems.len(),
+                report.file_count
+            );
+            for problem in report.problems.iter().take(MAX_LISTED_CHECKSUM_PROBLEMS) {
+                text.push_str(&format!("\n{}", problem));
+            }
+            if report.problems.len() > MAX_LISTED_CHECKSUM_PROBLEMS {
+                text.push_str(&format!(
+                    "\n...and {} more",
+                    report.problems.len() - MAX_LISTED_CHECKSUM_PROBLEMS
+                ));
+            }
+            Some(text)
+        }
+        Some(Err(error)) => Some(format!("Failed to verify files: {}", error)),
+    }
+}
+
+/// Verifies the checksums of a modpack in the background, unless it was already verified.
+/// Only one modpack is verified at a time.
+unsafe fn start_verification(modpack: &Modpack) {
+    let verified = GLOBALS
+        .verification_results
+        .iter()
+        .any(|(path, _)| path == &modpack.path);
+    if !verified && GLOBALS.verification.is_none() {
+        GLOBALS.verification = Some(checksums::start_verify(modpack.path.clone()));
+    }
+}
+
+unsafe fn update_verification() {
+    let result = match &GLOBALS.verification {
+        Some(receiver) => receiver.try_recv(),
+        None => return,
+    };
+    if let Ok((path, result)) = result {
+        match &result {
+            Ok(Some(report)) => {
+                for problem in &report.problems {
+                    eprintln!("[hyperbeam-launcher] {}: {}", path.display(), problem);
+                }
+            }
+            Ok(None) => {}
+            Err(error) => eprintln!(
+                "[hyperbeam-launcher] Failed to verify {}: {}",
+                path.display(),
+                error
+            ),
+        }
+        GLOBALS.verification = None;
+        GLOBALS.verification_results.push((path, result));
+
+        // Show the result or verify the modpack that was opened in the meantime
+        if let (
+            State::ModpackDetails {
+                screenshots,
+                screenshot_index,
+                ..
+            },
+            Some(modpack),
+        ) = (&GLOBALS.state, get_current_modpack())
+        {
+            show_modpack_details(modpack, screenshots.len(), *screenshot_index);
+            start_verification(modpack);
+        }
+    }
+}
+
+fn modpack_details_text(modpack: &Modpack) -> String {
+    let metadata = &modpack.metadata;
+    let mut text = format!(
+        "{}\nby {}\nVer. {}",
+        metadata.name, metadata.author, metadata.version
+    );
+    if let Some(description) = &metadata.description {
+        text.push_str(&format!("\n\n{}", description.trim()));
+    }
+    if !metadata.credits.is_empty() {
+        text.push_str(&format!("\n\nCredits: {}", metadata.credits.join(", ")));
+    }
+    if let Some(license) = &metadata.license {
+        text.push_str(&format!("\nLicense: {}", license));
+    }
+    if let Some(website) = &metadata.website {
+        text.push_str(&format!("\nWebsite: {}", website));
+    }
+    if let Some(changelog) = &metadata.changelog {
+        text.push_str(&format!("\n\nChangelog:\n{}", changelog.trim()));
+    }
+    if !modpack.warnings.is_empty() {
+        text.push('\n');
+        for warning in &modpack.warnings {
+            text.push_str(&format!("\n{}: {}", warning.severity(), warning));
+        }
+    }
+    if let Some(verification_text) = verification_text(modpack) {
+        text.push_str(&format!("\n\n{}", verification_text));
+    }
+    text
+}
+
+unsafe fn splash_image_object() -> Option<*mut GameObject> {
+    find_splash_image()
+        .map(|splash_image| Component_1_get_gameObject(splash_image as _, null_mut()))
+}
+
+/// Shows a screenshot in the splash image, or hides the splash image if `screenshot` is `None`
+unsafe fn show_screenshot(screenshot: Option<NonNull<Texture2D>>) {
+    if let Some(splash_image) = find_splash_image() {
+        let texture = screenshot.map(NonNull::as_ptr).unwrap_or(null_mut());
+        RawImage_set_texture(splash_image, texture as _, null_mut());
+        GameObject_SetActive(
+            Component_1_get_gameObject(splash_image as _, null_mut()),
+            screenshot.is_some(),
+            null_mut(),
+        );
+    }
+}
+
+unsafe fn destroy_screenshot(screenshot: Option<NonNull<Texture2D>>) {
+    if let Some(screenshot) = screenshot {
+        Object_1_Destroy_1(screenshot.as_ptr() as _, null_mut());
+    }
+}
+
+unsafe fn load_screenshot(modpack: &Modpack, name: &str) -> Option<NonNull<Texture2D>> {
+    match modpack.load_screenshot(name) {
+        Ok(screenshot) => Some(screenshot),
+        Err(error) => {
+            eprintln!(
+                "[hyperbeam-launcher] Failed to load screenshot {}: {}",
+                name, error
+            );
+            None
+        }
+    }
+}
+
+unsafe fn show_modpack_details(
+    modpack: &Modpack,
+    screenshot_count: usize,
+    screenshot_index: usize,
+) {
+    let mut controls = if screenshot_count > 1 {
+        format!(
+            "L/R: Screenshot {}/{}    ",
+            screenshot_index + 1,
+            screenshot_count
+        )
+    } else {
+        String::new()
+    };
+    if !optional_plugins(modpack).is_empty() {
+        controls.push_str("Y: Plugins    ");
+    }
+    controls.push_str("B: Close");
+    show_overlay(format!("{}\n\n{}", modpack_details_text(modpack), controls));
+}
+
+unsafe fn open_modpack_details(modpack: &Modpack) {
+    let screenshots = modpack.screenshot_names();
+    // The splash image is normally only shown by the launch animation
+    let splash_image_was_active = splash_image_object()
+        .map(|splash_image| GameObject_get_activeSelf(splash_image, null_mut()))
+        .unwrap_or(false);
+    let screenshot = screenshots
+        .first()
+        .and_then(|name| load_screenshot(modpack, name));
+    show_screenshot(screenshot);
+
+    start_verification(modpack);
+    show_modpack_details(modpack, screenshots.len(), 0);
+    GLOBALS.state = State::ModpackDetails {
+        screenshots,
+        screenshot_index: 0,
+        screenshot,
+        splash_image_was_active,
+    };
+}
+
+unsafe fn update_modpack_details() {
+    let modpack = match get_current_modpack() {
+        Some(modpack) => modpack,
+        None => return,
+    };
+
+    if let State::ModpackDetails {
+        screenshots,
+        screenshot_index,
+        screenshot,
+        splash_image_was_active,
+    } = &mut GLOBALS.state
+    {
+        let close = input::get_button_down(input::Button::B);
+        let show_plugins =
+            input::get_button_down(input::Button::Y) && !optional_plugins(modpack).is_empty();
+        if close || show_plugins {
+            show_screenshot(None);
+            if let Some(splash_image) = splash_image_object() {
+                GameObject_SetActive(splash_image, *splash_image_was_active, null_mut());
+            }
+            destroy_screenshot(screenshot.take());
+            hide_overlay();
+            GLOBALS.state = State::ModpackSelect;
+            if show_plugins {
+                open_plugin_settings(modpack);
+            }
+            return;
+        }
+
+        let previous_index = *screenshot_index;
+        if input::get_button_down(input::Button::L) && *screenshot_index > 0 {
+            *screenshot_index -= 1;
+        } else if input::get_button_down(input::Button::R)
+            && *screenshot_index + 1 < screenshots.len()
+        {
+            *screenshot_index += 1;
+        }
+
+        if *screenshot_index != previous_index {
+            destroy_screenshot(screenshot.take());
+            *screenshot = load_screenshot(modpack, &screenshots[*screenshot_index]);
+            show_screenshot(*screenshot);
+            show_modpack_details(modpack, screenshots.len(), *screenshot_index);
+        }
+    }
+}
+
+fn optional_plugins(modpack: &Modpack) -> Vec<String> {
+    modpack
+        .plugins()
+        .into_iter()
+        .filter(|plugin| plugin.optional)
+        .map(|plugin| plugin.name)
+        .collect()
+}
+
+unsafe fn show_plugin_settings(plugins: &[String], config: &ModpackConfig, selection: usize) {
+    let lines: Vec<String> = plugins
+        .iter()
+        .enumerate()
+        .map(|(i, plugin)| {
+            let marker = if i == selection { "> " } else { "" };
+            let checkbox = if config.is_plugin_enabled(plugin) {
+                "[x]"
+            } else {
+                "[ ]"
+            };
+            format!("{}{} {}", marker, checkbox, plugin)
+        })
+        .collect();
+    show_overlay(format!(
+        "Optional plugins\n\n{}\n\nA: Enable/disable    B: Back",
+        lines.join("\n")
+    ));
+}
+
+unsafe fn open_plugin_settings(modpack: &Modpack) {
+    let plugins = optional_plugins(modpack);
+    let config = ModpackConfig::load(&modpack.path);
+    show_plugin_settings(&plugins, &config, 0);
+    GLOBALS.state = State::PluginSettings {
+        plugins,
+        config,
+        selection: 0,
+    };
+}
+
+unsafe fn update_plugin_settings() {
+    let modpack = match get_current_modpack() {
+        Some(modpack) => modpack,
+        None => return,
+    };
+
+    if let State::PluginSettings {
+        plugins,
+        config,
+        selection,
+    } = &mut GLOBALS.state
+    {
+        if input::get_button_down(input::Button::B) {
+            hide_overlay();
+            open_modpack_details(modpack);
+            return;
+        } else if input::get_button_down(input::Button::Up) && *selection > 0 {
+            *selection -= 1;
+        } else if input::get_button_down(input::Button::Down) && *selection + 1 < plugins.len() {
+            *selection += 1;
+        } else if input::get_button_down(input::Button::A) {
+            let plugin = &plugins[*selection];
+            config.set_plugin_enabled(plugin, !config.is_plugin_enabled(plugin));
+            if let Err(error) = config.save(&modpack.path) {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to save modpack config: {}",
+                    error
+                );
+                show_message(format!("Failed to save the plugin settings:\n{}", error));
+                return;
+            }
+        }
+        show_plugin_settings(plugins, config, *selection);
+    }
+}
+
 unsafe fn show_selected_modpack() {
     let (title_string, version_string) = match GLOBALS.selection_index {
         0 => (
@@ -335,15 +1240,104 @@ fn get_current_modpack() -> Option<&'static Modpack> {
     }
 }
 
+fn get_current_invalid_modpack() -> Option<&'static InvalidModpack> {
+    unsafe {
+        if GLOBALS.selection_index == 0 {
+            return None;
+        }
+        if let Some(ModpackLoadResult::Invalid(invalid_modpack)) =
+            &GLOBALS.modpacks.get(GLOBALS.selection_index as usize - 1)
+        {
+            Some(invalid_modpack)
+        } else {
+            None
+        }
+    }
+}
+
 fn selected_modpack_loadable() -> bool {
-    unsafe { GLOBALS.selection_index == 0 || get_current_modpack().is_some() }
+    unsafe {
+        GLOBALS.selection_index == 0
+            || get_current_modpack()
+                .map(|modpack| modpack::resolve_load_order(modpack, &GLOBALS.modpacks).is_ok())
+                .unwrap_or(false)
+    }
+}
+
+/// Returns the plugins the modpack would load that aren't signed by a trusted author.
+fn untrusted_plugins(modpack: &Modpack) -> Vec<UntrustedPlugin> {
+    let trusted_keys = plugin_signature::parse_trusted_keys(&config::get_config().trusted_keys);
+    match modpack::resolve_load_order(modpack, unsafe { &GLOBALS.modpacks }) {
+        Ok(layers) => modpack::untrusted_plugins(&layers, &trusted_keys),
+        Err(_) => Vec::new(),
+    }
+}
+
+unsafe fn confirm_untrusted_plugins(plugins: &[UntrustedPlugin]) {
+    let mut text = "This modpack contains plugins that aren't signed by a trusted author.\n\
+        Plugins have full access to your console.\n"
+        .to_owned();
+    for plugin in plugins {
+        text.push_str(&format!(
+            "\n{}/{} ({})",
+            plugin.modpack_id, plugin.name, plugin.trust
+        ));
+    }
+    show_overlay(format!("{}\n\nA: Launch anyway  B: Cancel", text));
+    GLOBALS.state = State::ConfirmUntrustedPlugins;
+}
+
+unsafe fn start_launch() {
+    GLOBALS.state = State::PreLoadingAnimation;
+    show_splash_image();
+    Animation_Play_3(
+        GLOBALS.launcher_animation,
+        IlString::new("ShowSplashImage").as_ptr(),
+        null_mut(),
+    );
 }
 
-unsafe fn load_modpack(modpack: &'static Modpack) {
+/// Loads the modpack and its plugins. Returns the plugins that aren't required and failed to load.
+unsafe fn load_modpack(modpack: &'static Modpack) -> Result<Vec<PluginFailure>, Box<dyn Error>> {
     println!("[hyperbeam-launcher] Loading modpack: {:?}", modpack);
+    let layers = modpack::resolve_load_order(modpack, &GLOBALS.modpacks)?;
+    println!(
+        "[hyperbeam-launcher] Modpack layers: {:?}",
+        layers
+            .iter()
+            .map(|layer| &layer.metadata.id)
+            .collect::<Vec<_>>()
+    );
+
+    if !modpack.metadata.shared_save {
+        save_data::isolate_save_data(&modpack.metadata.id)?;
+    }
+
+    // Plugins query the current modpack from their main function, so it needs to be set before loading them
+    GLOBALS.loaded_modpack = Some(CurrentModpack {
+        metadata: modpack.metadata.clone(),
+        path: modpack.path.clone(),
+        layers: layers
+            .iter()
+            .map(|layer| ModpackLayer {
+                metadata: layer.metadata.clone(),
+                path: layer.path.clone(),
+            })
+            .collect(),
+    });
     println!("[hyperbeam-launcher] Loading modpack plugins...");
-    modpack.load_plugins();
-    GLOBALS.loaded_modpack = Some(&modpack);
+    let failures = modpack::load_plugins(&layers);
+    api::fire_event(events::PLUGINS_LOADED);
+    Ok(failures)
+}
+
+unsafe fn confirm_plugin_failures(failures: &[PluginFailure]) {
+    let mut text = "These plugins failed to load and were skipped:\n".to_owned();
+    for failure in failures {
+        text.push_str(&format!("\n{}/{}", failure.modpack_id, failure.name));
+    }
+    show_overlay(format!("{}\n\nA: Continue", text));
+    GLOBALS.state = State::ConfirmPluginFailures;
 }
 
 unsafe fn load_game() {
@@ -381,7 +1375,11 @@ unsafe fn hook_native_plugin_manager_start(this_ptr: *mut NativePluginManager) {
     show_selected_modpack();
     nn::oe::FinishStartupLogo();
 
-    start_update_check();
+    if installer::pending_archives().is_empty() {
+        start_update_check();
+    } else {
+        start_install();
+    }
 }
 
 #[hook(replace = ScriptDataManager_OnEnable)]
@@ -419,27 +1417,33 @@ fn hook_startup_sequence_main_flow(
     method: *mut MethodInfo,
 ) -> *mut IEnumerator {
     unsafe {
-        Object_1_Destroy_1(GLOBALS.launcher_ui as _, null_mut());
-        Object_1_Destroy_1(GLOBALS.vanilla_icon as _, null_mut());
-        if !GLOBALS.splash_image.is_null() {
-            Object_1_Destroy_1(GLOBALS.splash_image as _, null_mut());
-        }
-        let iter = GLOBALS
-            .modpacks
-            .iter_mut()
-            .filter_map(|modpack| match modpack {
-                ModpackLoadResult::Success(modpack) => Some(modpack),
-                _ => None,
-            })
-            .for_each(|modpack| modpack.unload_icon());
-        GLOBALS.state = State::Loaded;
+        // Auto-launched modpacks are loaded without showing the launcher
+        if GLOBALS.state != State::Loaded {
+            Object_1_Destroy_1(GLOBALS.launcher_ui as _, null_mut());
+            Object_1_Destroy_1(GLOBALS.vanilla_icon as _, null_mut());
+            if !GLOBALS.splash_image.is_null() {
+                Object_1_Destroy_1(GLOBALS.splash_image as _, null_mut());
+            }
+            let iter = GLOBALS
+                .modpacks
+                .iter_mut()
+                .filter_map(|modpack| match modpack {
+                    ModpackLoadResult::Success(modpack) => Some(modpack),
+                    _ => None,
+                })
+                .for_each(|modpack| modpack.unload_icon());
+            GLOBALS.state = State::Loaded;
+        }
     }
-    call_original!(this_ptr, method)
+    let main_loop = call_original!(this_ptr, method);
+    api::fire_event(events::GAME_FLOW_STARTED);
+    main_loop
 }
 
 #[hook(replace = GroundManager_Update)]
 unsafe fn hook_ground_manager_update(_this_ptr: *mut GroundManager) {
     if GLOBALS.state == State::Loaded {
+        api::update_location(Location::Ground);
         return;
     }
 
@@ -453,43 +1457,14 @@ unsafe fn hook_ground_manager_update(_this_ptr: *mut GroundManager) {
 
     let dt = Time_get_deltaTime(null_mut());
 
-    let show_splash_image_anim_name = IlString::new("ShowSplashImage").as_ptr();
     let launcher_animation_playing =
         Animation_get_isPlaying(GLOBALS.launcher_animation, null_mut());
 
+    update_verification();
+
     match &GLOBALS.state {
-        State::UpdateCheck(receiver) => {
-            if let Ok(update_check_result) = receiver.try_recv() {
-                match update_check_result {
-                    Ok(update_check_result) => {
-                        match update_check_result {
-                            UpdateCheckResult::UpdateAvailable(update) => {
-                                //update.start_update();
-                                GLOBALS.state = State::ModpackSelect;
-                                GameObject_SetActive(
-                                    GLOBALS.pending_operation_bg,
-                                    false,
-                                    null_mut(),
-                                );
-                                GameObject_SetActive(GLOBALS.main_container, true, null_mut());
-                            }
-                            UpdateCheckResult::NoUpdate => {
-                                GLOBALS.state = State::ModpackSelect;
-                                GameObject_SetActive(
-                                    GLOBALS.pending_operation_bg,
-                                    false,
-                                    null_mut(),
-                                );
-                                GameObject_SetActive(GLOBALS.main_container, true, null_mut());
-                            }
-                        };
-                    }
-                    Err(error) => {
-                        eprintln!("Update check error: {:?}", error)
-                    }
-                };
-            }
-        }
+        State::Installing(receiver) => update_install_progress(receiver),
+        State::UpdateCheck(receiver) => update_update_check(receiver, dt),
         State::ModpackSelect => {
             if !launcher_animation_playing {
                 if input::get_button(input::Button::Left) {
@@ -514,26 +1489,129 @@ unsafe fn hook_ground_manager_update(_this_ptr: *mut GroundManager) {
                         );
                     }
                 }
+                if let Some(modpack) = get_current_modpack() {
+                    if !modpack.metadata.shared_save {
+                        if input::get_button_down(input::Button::L) {
+                            confirm_save_transfer(modpack, SaveTransfer::VanillaToModpack);
+                            return;
+                        } else if input::get_button_down(input::Button::R) {
+                            confirm_save_transfer(modpack, SaveTransfer::ModpackToVanilla);
+                            return;
+                        }
+                    }
+                }
+                if input::get_button_down(input::Button::X) {
+                    if let Some(modpack) = get_current_modpack() {
+                        open_modpack_details(modpack);
+                        return;
+                    }
+                }
+                if input::get_button_down(input::Button::Y) && selected_modpack_loadable() {
+                    open_save_backups(get_current_modpack());
+                    return;
+                }
+                if input::get_button_down(input::Button::A) {
+                    if let Some(invalid_modpack) = get_current_invalid_modpack() {
+                        show_broken_modpack(invalid_modpack);
+                        return;
+                    }
+                    if let Some(modpack) = get_current_modpack() {
+                        if let Err(error) = modpack::resolve_load_order(modpack, &GLOBALS.modpacks)
+                        {
+                            show_message(format!("This modpack can't be launched:\n{}", error));
+                            return;
+                        }
+                    }
+                }
                 if input::get_button_down(input::Button::A) && selected_modpack_loadable() {
-                    GLOBALS.state = State::PreLoadingAnimation;
-                    show_splash_image();
-                    Animation_Play_3(
-                        GLOBALS.launcher_animation,
-                        show_splash_image_anim_name,
-                        null_mut(),
-                    );
+                    if let Some(modpack) = get_current_modpack() {
+                        let untrusted_plugins = untrusted_plugins(modpack);
+                        if !untrusted_plugins.is_empty() {
+                            confirm_untrusted_plugins(&untrusted_plugins);
+                            return;
+                        }
+                    }
+                    start_launch();
+                }
+            }
+        }
+        State::ConfirmSaveTransfer(transfer) => {
+            let transfer = *transfer;
+            if input::get_button_down(input::Button::A) {
+                if let Some(modpack) = get_current_modpack() {
+                    transfer_save_data(modpack, transfer);
                 }
+            } else if input::get_button_down(input::Button::B) {
+                hide_overlay();
+                GLOBALS.state = State::ModpackSelect;
+            }
+        }
+        State::SaveBackups { .. } => update_save_backups(),
+        State::ModpackDetails { .. } => update_modpack_details(),
+        State::PluginSettings { .. } => update_plugin_settings(),
+        State::Message => {
+            if input::get_button_down(input::Button::B) {
+                hide_overlay();
+                GLOBALS.state = State::ModpackSelect;
+            }
+        }
+        State::ConfirmUntrustedPlugins => {
+            if input::get_button_down(input::Button::A) {
+                hide_overlay();
+                start_launch();
+            } else if input::get_button_down(input::Button::B) {
+                hide_overlay();
+                GLOBALS.state = State::ModpackSelect;
+            }
+        }
+        State::UpdateAvailable => {
+            if input::get_button_down(input::Button::A) {
+                start_self_update();
+            } else if input::get_button_down(input::Button::X) {
+                skip_update();
+            } else if input::get_button_down(input::Button::B) {
+                hide_overlay();
+                GLOBALS.state = State::ModpackSelect;
+            }
+        }
+        State::Updating(receiver) => update_self_update_progress(receiver, dt),
+        State::UpdateFailed => {
+            if input::get_button_down(input::Button::A) {
+                start_self_update();
+            } else if input::get_button_down(input::Button::B) {
+                hide_overlay();
+                GLOBALS.state = State::ModpackSelect;
             }
         }
         State::PreLoadingAnimation => {
             if !launcher_animation_playing {
                 GLOBALS.state = State::Loading;
+                backup_save_data(get_current_modpack());
                 if let Some(modpack) = get_current_modpack() {
-                    load_modpack(modpack);
+                    match load_modpack(modpack) {
+                        Ok(failures) if !failures.is_empty() => {
+                            confirm_plugin_failures(&failures);
+                            return;
+                        }
+                        Ok(_) => {}
+                        Err(error) => {
+                            eprintln!("[hyperbeam-launcher] Failed to load modpack: {}", error);
+                            hide_splash_image();
+                            show_message(format!("Failed to load the modpack:\n{}", error));
+                            return;
+                        }
+                    }
                 }
                 load_game();
             }
         }
+        State::ConfirmPluginFailures => {
+            if input::get_button_down(input::Button::A) {
+                hide_overlay();
+                GLOBALS.state = State::Loading;
+                load_game();
+            }
+        }
         _ => {}
     };
 }
@@ -548,10 +1626,18 @@ fn install_launcher_hooks() {
     install_hooks!(hook_native_plugin_manager_start, hook_ground_manager_update);
 }
 
+/// Installs the hooks that fire the game flow and location events after auto-launching, when
+/// the launcher hooks aren't installed
+fn install_event_hooks() {
+    install_hooks!(hook_startup_sequence_main_flow, hook_ground_manager_update);
+}
+
 unsafe fn auto_launch(id: &str) {
     if id == "vanilla" {
         // If we're auto-launching vanilla, nothing else needs to be done
         println!("[hyperbeam-launcher] Launching vanilla.");
+        backup_save_data(None);
+        update_installer::confirm_update();
         return;
     } else if let Some(ModpackLoadResult::Success(modpack)) =
         GLOBALS.modpacks.iter().find(|modpack| match modpack {
@@ -559,7 +1645,34 @@ unsafe fn auto_launch(id: &str) {
             ModpackLoadResult::Invalid(_) => false,
         })
     {
-        load_modpack(modpack);
+        let untrusted_plugins = untrusted_plugins(modpack);
+        if !untrusted_plugins.is_empty() {
+            // Untrusted plugins need to be confirmed in the UI
+            for plugin in untrusted_plugins {
+                eprintln!(
+                    "[hyperbeam-launcher] Not auto-launching, plugin {}/{} is {}",
+                    plugin.modpack_id, plugin.name, plugin.trust
+                );
+            }
+            install_launcher_hooks();
+            return;
+        }
+        backup_save_data(Some(modpack));
+        match load_modpack(modpack) {
+            // The modpack selection is skipped, so the update is confirmed once a modpack loads
+            Ok(_) => {
+                update_installer::confirm_update();
+                GLOBALS.state = State::Loaded;
+                install_event_hooks();
+            }
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to auto-launch modpack: {}",
+                    error
+                );
+                install_launcher_hooks();
+            }
+        }
     } else {
         // Failed to auto-launch, show UI instead
         install_launcher_hooks();
@@ -569,11 +1682,16 @@ unsafe fn auto_launch(id: &str) {
 #[skyline::main(name = "hyperbeam_launcher")]
 pub unsafe fn main() {
     println!("???????????? OLD VERSION");
+    update_installer::check_installed_update();
     let launch_config = config::get_config();
     println!(
         "[hyperbeam_launcher] Initializing with config: {:?}",
         launch_config
     );
+    if launch_config.auto_launch.is_some() {
+        // The launcher UI isn't shown, so install new modpacks before they're loaded
+        installer::install_inbox(|_| {});
+    }
     GLOBALS.modpacks = modpack::load_all_modpacks().expect("Failed to load modpacks!");
 
     if let Some(auto_launch_id) = &launch_config.auto_launch {
@@ -582,12 +1700,3 @@ pub unsafe fn main() {
         install_launcher_hooks();
     }
 }
-
-#[no_mangle]
-fn hbGetCurrentModpackMetadata() -> Option<ModpackMetadata> {
-    unsafe {
-        GLOBALS
-            .loaded_modpack
-            .map(|modpack| modpack.metadata.clone())
-    }
-}
@@ -0,0 +1,404 @@
+use hyperbeam_rtdx::modpack::{ModpackMetadata, ModpackReference};
+use semver::VersionReq;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum LoadProblem {
+    MissingLayer {
+        modpack: String,
+        layer: String,
+    },
+    MissingDependency {
+        modpack: String,
+        dependency: String,
+        requirement: VersionReq,
+    },
+    WrongDependencyVersion {
+        modpack: String,
+        dependency: String,
+        requirement: VersionReq,
+        installed: semver::Version,
+    },
+    Conflict {
+        modpack: String,
+        conflicting: String,
+    },
+    Cycle(Vec<String>),
+}
+
+impl fmt::Display for LoadProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadProblem::MissingLayer { modpack, layer } => write!(
+                f,
+                "\"{}\" is layered on \"{}\", which is missing or broken",
+                modpack, layer
+            ),
+            LoadProblem::MissingDependency {
+                modpack,
+                dependency,
+                requirement,
+            } => write!(
+                f,
+                "\"{}\" requires \"{}\" ({}), which is missing or broken",
+                modpack, dependency, requirement
+            ),
+            LoadProblem::WrongDependencyVersion {
+                modpack,
+                dependency,
+                requirement,
+                installed,
+            } => write!(
+                f,
+                "\"{}\" requires \"{}\" {}, but version {} is installed",
+                modpack, dependency, requirement, installed
+            ),
+            LoadProblem::Conflict {
+                modpack,
+                conflicting,
+            } => write!(
+                f,
+                "\"{}\" can't be loaded together with \"{}\"",
+                modpack, conflicting
+            ),
+            LoadProblem::Cycle(ids) => write!(
+                f,
+                "The load order of {} contradicts itself",
+                ids.iter()
+                    .map(|id| format!("\"{}\"", id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// All problems that prevent a modpack from being loaded
+#[derive(Debug, Eq, PartialEq)]
+pub struct LoadOrderError {
+    pub problems: Vec<LoadProblem>,
+}
+
+impl Error for LoadOrderError {}
+
+impl fmt::Display for LoadOrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lines: Vec<String> = self.problems.iter().map(ToString::to_string).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+fn find<'a>(available: &[&'a ModpackMetadata], id: &str) -> Option<&'a ModpackMetadata> {
+    available.iter().copied().find(|metadata| metadata.id == id)
+}
+
+fn matches_reference(metadata: &ModpackMetadata, reference: &ModpackReference) -> bool {
+    metadata.id == reference.id && reference.version.matches(&metadata.version)
+}
+
+/// Computes the set of modpacks to load for the selected modpack and their priority order.
+///
+/// The load set contains the selected modpack along with all its layers and dependencies,
+/// recursively. Layers and dependencies are loaded below the modpack that requires them,
+/// `loadAfter` and `loadBefore` only affect the order of modpacks that are in the load set.
+/// Returns the load set with the highest priority first, or every problem that was found.
+pub fn resolve_load_order<'a>(
+    selected: &'a ModpackMetadata,
+    available: &[&'a ModpackMetadata],
+) -> Result<Vec<&'a ModpackMetadata>, LoadOrderError> {
+    let mut problems = Vec::new();
+
+    // Collect the load set in discovery order
+    let mut load_set: Vec<&ModpackMetadata> = vec![selected];
+    let mut queue = VecDeque::from(vec![selected]);
+    while let Some(modpack) = queue.pop_front() {
+        let mut required = Vec::new();
+        for layer in &modpack.layers {
+            match find(available, layer) {
+                Some(metadata) => required.push(metadata),
+                None => problems.push(LoadProblem::MissingLayer {
+                    modpack: modpack.id.clone(),
+                    layer: layer.clone(),
+                }),
+            }
+        }
+        for dependency in &modpack.dependencies {
+            match find(available, &dependency.id) {
+                Some(metadata) if dependency.version.matches(&metadata.version) => {
+                    required.push(metadata)
+                }
+                Some(metadata) => problems.push(LoadProblem::WrongDependencyVersion {
+                    modpack: modpack.id.clone(),
+                    dependency: dependency.id.clone(),
+                    requirement: dependency.version.clone(),
+                    installed: metadata.version.clone(),
+                }),
+                None => problems.push(LoadProblem::MissingDependency {
+                    modpack: modpack.id.clone(),
+                    dependency: dependency.id.clone(),
+                    requirement: dependency.version.clone(),
+                }),
+            }
+        }
+
+        for metadata in required {
+            if !load_set.iter().any(|loaded| loaded.id == metadata.id) {
+                load_set.push(metadata);
+                queue.push_back(metadata);
+            }
+        }
+    }
+
+    for modpack in &load_set {
+        for other in &load_set {
+            if modpack
+                .conflicts
+                .iter()
+                .any(|conflict| matches_reference(other, conflict))
+            {
+                problems.push(LoadProblem::Conflict {
+                    modpack: modpack.id.clone(),
+                    conflicting: other.id.clone(),
+                });
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(LoadOrderError { problems });
+    }
+
+    // Edges point from a modpack to the modpacks that must be loaded after it
+    let index_of = |id: &str| load_set.iter().position(|metadata| metadata.id == id);
+    let mut loaded_after: Vec<Vec<usize>> = vec![Vec::new(); load_set.len()];
+    for (i, modpack) in load_set.iter().enumerate() {
+        let below = modpack
+            .layers
+            .iter()
+            .chain(modpack.dependencies.iter().map(|dependency| &dependency.id))
+            .chain(modpack.load_after.iter());
+        for id in below {
+            if let Some(j) = index_of(id) {
+                loaded_after[j].push(i);
+            }
+        }
+        // Layers are listed from lowest to highest priority
+        for pair in modpack.layers.windows(2) {
+            if let (Some(lower), Some(higher)) = (index_of(&pair[0]), index_of(&pair[1])) {
+                loaded_after[lower].push(higher);
+            }
+        }
+        for id in &modpack.load_before {
+            if let Some(j) = index_of(id) {
+                loaded_after[i].push(j);
+            }
+        }
+    }
+
+    // Topological sort that keeps the discovery order where possible, so that the selected
+    // modpack stays on top unless another modpack explicitly needs to be above it
+    let mut incoming = vec![0; load_set.len()];
+    for targets in &loaded_after {
+        for &target in targets {
+            incoming[target] += 1;
+        }
+    }
+    let mut order = Vec::with_capacity(load_set.len());
+    let mut done = vec![false; load_set.len()];
+    while order.len() < load_set.len() {
+        // Modpacks discovered last are loaded first, since they're the deepest requirements
+        let next = (0..load_set.len())
+            .rev()
+            .find(|&i| !done[i] && incoming[i] == 0);
+        match next {
+            Some(i) => {
+                done[i] = true;
+                order.push(load_set[i]);
+                for &target in &loaded_after[i] {
+                    incoming[target] -= 1;
+                }
+            }
+            None => {
+                let cycle = (0..load_set.len())
+                    .filter(|&i| !done[i])
+                    .map(|i| load_set[i].id.clone())
+                    .collect();
+                return Err(LoadOrderError {
+                    problems: vec![LoadProblem::Cycle(cycle)],
+                });
+            }
+        }
+    }
+
+    order.reverse();
+    Ok(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses modpack metadata from the YAML keys that affect the load order
+    fn modpack(id: &str, version: &str, keys: &str) -> ModpackMetadata {
+        serde_yaml::from_str(&format!(
+            "id: {}\nname: {}\nauthor: test\nversion: {}\ntarget: RTDX\n{}",
+            id, id, version, keys
+        ))
+        .unwrap()
+    }
+
+    fn resolve<'a>(
+        selected: &'a ModpackMetadata,
+        available: &[&'a ModpackMetadata],
+    ) -> Result<Vec<&'a str>, LoadOrderError> {
+        resolve_load_order(selected, available)
+            .map(|order| order.iter().map(|metadata| metadata.id.as_str()).collect())
+    }
+
+    fn problems(result: Result<Vec<&str>, LoadOrderError>) -> Vec<LoadProblem> {
+        result.unwrap_err().problems
+    }
+
+    #[test]
+    fn single_modpack() {
+        let selected = modpack("selected", "1.0.0", "");
+        let other = modpack("other", "1.0.0", "");
+        assert_eq!(
+            resolve(&selected, &[&selected, &other]).unwrap(),
+            ["selected"]
+        );
+    }
+
+    #[test]
+    fn dependencies_and_layers_are_loaded_below() {
+        let selected = modpack(
+            "selected",
+            "1.0.0",
+            "layers: [base, extra]\ndependencies: [{ id: library }]",
+        );
+        let base = modpack("base", "1.0.0", "");
+        let extra = modpack("extra", "1.0.0", "");
+        let library = modpack("library", "2.0.0", "dependencies: [{ id: core }]");
+        let core = modpack("core", "1.0.0", "");
+        assert_eq!(
+            resolve(&selected, &[&core, &library, &extra, &base, &selected]).unwrap(),
+            ["selected", "extra", "base", "library", "core"]
+        );
+    }
+
+    #[test]
+    fn load_after_and_load_before() {
+        let selected = modpack("selected", "1.0.0", "dependencies: [{ id: a }, { id: b }]");
+        let a = modpack("a", "1.0.0", "");
+        let b = modpack("b", "1.0.0", "");
+        assert_eq!(
+            resolve(&selected, &[&selected, &a, &b]).unwrap(),
+            ["selected", "a", "b"]
+        );
+
+        let b_after_a = modpack("b", "1.0.0", "loadAfter: [a, not-loaded]");
+        assert_eq!(
+            resolve(&selected, &[&selected, &a, &b_after_a]).unwrap(),
+            ["selected", "b", "a"]
+        );
+
+        let a_before_b = modpack("a", "1.0.0", "loadBefore: [b, not-loaded]");
+        assert_eq!(
+            resolve(&selected, &[&selected, &a_before_b, &b]).unwrap(),
+            ["selected", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn contradicting_load_order() {
+        let selected = modpack("selected", "1.0.0", "dependencies: [{ id: overlay }]");
+        let overlay = modpack("overlay", "1.0.0", "loadAfter: [selected]");
+        assert_eq!(
+            problems(resolve(&selected, &[&selected, &overlay])),
+            [LoadProblem::Cycle(vec![
+                "selected".to_owned(),
+                "overlay".to_owned()
+            ])]
+        );
+
+        // Modpacks that are loaded after the cycle are reported with it
+        let selected = modpack("selected", "1.0.0", "layers: [base, patch]");
+        let base = modpack("base", "1.0.0", "");
+        let patch = modpack("patch", "1.0.0", "loadBefore: [base]");
+        assert_eq!(
+            problems(resolve(&selected, &[&selected, &base, &patch])),
+            [LoadProblem::Cycle(vec![
+                "selected".to_owned(),
+                "base".to_owned(),
+                "patch".to_owned()
+            ])]
+        );
+    }
+
+    #[test]
+    fn dependency_cycle() {
+        let selected = modpack("selected", "1.0.0", "dependencies: [{ id: a }]");
+        let a = modpack("a", "1.0.0", "dependencies: [{ id: b }]");
+        let b = modpack("b", "1.0.0", "dependencies: [{ id: a }]");
+        assert_eq!(
+            problems(resolve(&selected, &[&selected, &a, &b])),
+            [LoadProblem::Cycle(vec![
+                "selected".to_owned(),
+                "a".to_owned(),
+                "b".to_owned()
+            ])]
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let selected = modpack(
+            "selected",
+            "1.0.0",
+            "dependencies: [{ id: a }, { id: b }]\nconflicts: [{ id: b, version: '<1' }]",
+        );
+        let a = modpack("a", "1.0.0", "conflicts: [{ id: b }, { id: not-loaded }]");
+        let b = modpack("b", "1.2.0", "");
+        let not_loaded = modpack("not-loaded", "1.0.0", "");
+        assert_eq!(
+            problems(resolve(&selected, &[&selected, &a, &b, &not_loaded])),
+            [LoadProblem::Conflict {
+                modpack: "a".to_owned(),
+                conflicting: "b".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn missing_and_mismatched_requirements() {
+        let selected = modpack(
+            "selected",
+            "1.0.0",
+            "layers: [missing-layer]\n\
+             dependencies: [{ id: old, version: '>=2' }, { id: missing, version: '^1.1' }]",
+        );
+        let old = modpack("old", "1.5.0", "");
+        assert_eq!(
+            problems(resolve(&selected, &[&selected, &old])),
+            [
+                LoadProblem::MissingLayer {
+                    modpack: "selected".to_owned(),
+                    layer: "missing-layer".to_owned()
+                },
+                LoadProblem::WrongDependencyVersion {
+                    modpack: "selected".to_owned(),
+                    dependency: "old".to_owned(),
+                    requirement: VersionReq::parse(">=2").unwrap(),
+                    installed: semver::Version::new(1, 5, 0)
+                },
+                LoadProblem::MissingDependency {
+                    modpack: "selected".to_owned(),
+                    dependency: "missing".to_owned(),
+                    requirement: VersionReq::parse("^1.1").unwrap()
+                },
+            ]
+        );
+    }
+}
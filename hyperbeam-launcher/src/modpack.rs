@@ -1,26 +1,37 @@
-use hyperbeam_rtdx::modpack::{ModpackMetadata, MODPACK_BASE_PATH};
+use crate::load_order::{self, LoadOrderError};
+use crate::modpack_config::ModpackConfig;
+use crate::modpack_files;
+use crate::plugin_signature::{self, PluginTrust};
+use crate::self_update;
+use hyperbeam_rtdx::modpack::{
+    is_modpack_archive, ModpackMetadata, PluginEntry, MODPACK_BASE_PATH,
+};
 use hyperbeam_unity::texture_helpers;
 use image::GenericImageView;
 use pmdrtdx_bindings::Texture2D;
-use semver::Version;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Deserializer};
 use std::error::Error;
 use std::ffi::{CString, OsStr};
+use std::io::Cursor;
 use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
-use std::{fmt, fs};
+use std::{fmt, fs, io};
 
 #[derive(Debug)]
 pub struct Modpack {
     pub metadata: ModpackMetadata,
     pub path: PathBuf,
     pub icon: Option<Result<NonNull<Texture2D>, Box<dyn Error>>>,
+    /// Problems that don't prevent the modpack from being launched
+    pub warnings: Vec<ModpackError>,
 }
 
 #[derive(Debug)]
 pub struct InvalidModpack {
-    pub error: Box<dyn Error>,
+    /// All problems found in the modpack, including warnings
+    pub problems: Vec<ModpackError>,
     pub path: PathBuf,
 }
 
@@ -29,112 +40,409 @@ pub enum ModpackLoadResult {
     Invalid(InvalidModpack),
 }
 
-#[derive(Debug)]
-struct MissingManifestError;
-
-impl Error for MissingManifestError {}
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Severity {
+    /// The modpack can still be launched
+    Warning,
+    /// The modpack can't be launched
+    Error,
+}
 
-impl fmt::Display for MissingManifestError {
+impl fmt::Display for Severity {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "modpack.yaml missing in modpack root")
+        match self {
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Error => write!(f, "Error"),
+        }
     }
 }
 
+/// A problem found while validating a modpack
 #[derive(Debug)]
-struct IDMismatchError;
-
-impl Error for IDMismatchError {}
+pub enum ModpackError {
+    MissingManifest,
+    InvalidManifest(String),
+    UnknownKey(String),
+    InvalidVersion {
+        key: &'static str,
+        error: String,
+    },
+    UnsupportedLauncherVersion {
+        required: VersionReq,
+        installed: Version,
+    },
+    IDMismatch {
+        id: String,
+        folder_name: String,
+    },
+    WrongTarget(String),
+    InvalidImage {
+        file_name: &'static str,
+        error: String,
+    },
+    ImageDimensions {
+        file_name: &'static str,
+        expected_width: i32,
+        expected_height: i32,
+        width: i32,
+        height: i32,
+    },
+    EmptyRomfs,
+    MissingPlugin {
+        name: String,
+        required: bool,
+    },
+    UnlistedPlugin(String),
+}
 
-impl fmt::Display for IDMismatchError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Mismatch between folder name and modpack ID")
+impl ModpackError {
+    pub fn severity(&self) -> Severity {
+        match self {
+            ModpackError::UnknownKey(_)
+            | ModpackError::InvalidImage { .. }
+            | ModpackError::ImageDimensions { .. }
+            | ModpackError::EmptyRomfs
+            | ModpackError::MissingPlugin {
+                required: false, ..
+            }
+            | ModpackError::UnlistedPlugin(_) => Severity::Warning,
+            _ => Severity::Error,
+        }
     }
 }
 
-#[derive(Debug)]
-struct TargetError;
-
-impl Error for TargetError {}
+impl Error for ModpackError {}
 
-impl fmt::Display for TargetError {
+impl fmt::Display for ModpackError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Modpack target must be RTDX")
+        match self {
+            ModpackError::MissingManifest => write!(f, "modpack.yaml missing in modpack root"),
+            ModpackError::InvalidManifest(error) => write!(f, "Invalid modpack.yaml: {}", error),
+            ModpackError::UnknownKey(key) => write!(f, "Unknown key \"{}\" in modpack.yaml", key),
+            ModpackError::InvalidVersion { key, error } => {
+                write!(f, "Invalid version in \"{}\": {}", key, error)
+            }
+            ModpackError::UnsupportedLauncherVersion {
+                required,
+                installed,
+            } => write!(
+                f,
+                "The modpack requires hyperbeam {}, but version {} is installed",
+                required, installed
+            ),
+            ModpackError::IDMismatch { id, folder_name } => write!(
+                f,
+                "Mismatch between folder name \"{}\" and modpack ID \"{}\"",
+                folder_name, id
+            ),
+            ModpackError::WrongTarget(target) => {
+                write!(f, "Modpack target must be RTDX, not \"{}\"", target)
+            }
+            ModpackError::InvalidImage { file_name, error } => {
+                write!(f, "Failed to read {}: {}", file_name, error)
+            }
+            ModpackError::ImageDimensions {
+                file_name,
+                expected_width,
+                expected_height,
+                width,
+                height,
+            } => write!(
+                f,
+                "Wrong dimensions of {}, expected {}x{} but got {}x{}",
+                file_name, expected_width, expected_height, width, height
+            ),
+            ModpackError::EmptyRomfs => write!(f, "The romfs folder is missing or empty"),
+            ModpackError::MissingPlugin { name, .. } => write!(
+                f,
+                "Plugin \"{}\" is listed in modpack.yaml, but missing in the plugins folder",
+                name
+            ),
+            ModpackError::UnlistedPlugin(name) => write!(
+                f,
+                "Plugin \"{}\" isn't listed in modpack.yaml and won't be loaded",
+                name
+            ),
+        }
     }
 }
 
-#[derive(Debug)]
-struct ImageDimensionsError {
-    expected_width: i32,
-    expected_height: i32,
+pub fn has_errors(problems: &[ModpackError]) -> bool {
+    problems
+        .iter()
+        .any(|problem| problem.severity() == Severity::Error)
 }
 
-impl Error for ImageDimensionsError {}
-
-impl fmt::Display for ImageDimensionsError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Wrong image dimensions, expected {}x{}",
-            self.expected_width, self.expected_height
-        )
-    }
+/// The basic manifest fields, which are checked on their own before the full manifest is
+/// parsed, so that they are all reported even if the full manifest doesn't parse. This also
+/// reports manifests written for newer versions of hyperbeam as such.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestHeader {
+    id: Option<String>,
+    version: Option<String>,
+    target: Option<String>,
+    hyperbeam_version: Option<String>,
 }
 
+const PLUGINS_DIR: &str = "plugins";
+const ICON_FILE_NAME: &str = "icon.png";
+const ICON_WIDTH: i32 = 250;
+const ICON_HEIGHT: i32 = 250;
+const SPLASH_FILE_NAME: &str = "splash.png";
+const SPLASH_WIDTH: i32 = 1280;
+const SPLASH_HEIGHT: i32 = 720;
+
 extern "C" {
     fn add_plugin(name: *const c_char) -> bool;
     fn load_plugin_modules() -> bool;
 }
 
-impl Modpack {
-    fn new(path: &Path) -> Result<Modpack, Box<dyn Error>> {
-        let mut metadata_path = path.to_owned();
-        metadata_path.push(Path::new("modpack.yaml"));
-        if !metadata_path.is_file() {
-            return Err(Box::new(MissingManifestError {}));
-        }
-
-        let metadata_string = fs::read_to_string(&metadata_path)?;
-        let metadata: ModpackMetadata = serde_yaml::from_str(&metadata_string)?;
+/// Modpack archives are extracted here if a file can't be used directly from inside the archive
+const ARCHIVE_CACHE_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/cache";
+
+/// Returns the name a modpack's ID must match, which is the folder name or the archive name
+/// without extension.
+fn expected_id(path: &Path) -> &str {
+    let name = if is_modpack_archive(path) {
+        path.file_stem()
+    } else {
+        path.file_name()
+    };
+    name.and_then(OsStr::to_str).unwrap_or_default()
+}
 
-        let folder_name = path.file_name().and_then(OsStr::to_str).unwrap();
-        if folder_name != &metadata.id {
-            return Err(Box::new(IDMismatchError {}));
+/// Checks the basic manifest fields and parses the full manifest.
+/// Returns `None` if the manifest couldn't be parsed, in which case at least one error was added.
+fn read_metadata(path: &Path, problems: &mut Vec<ModpackError>) -> Option<ModpackMetadata> {
+    let metadata_string = match modpack_files::read_file(path, "modpack.yaml") {
+        Ok(Some(data)) => match String::from_utf8(data) {
+            Ok(metadata_string) => metadata_string,
+            Err(error) => {
+                problems.push(ModpackError::InvalidManifest(error.to_string()));
+                return None;
+            }
+        },
+        Ok(None) => {
+            problems.push(ModpackError::MissingManifest);
+            return None;
+        }
+        Err(error) => {
+            problems.push(ModpackError::InvalidManifest(error.to_string()));
+            return None;
         }
+    };
+    let header: ManifestHeader = match serde_yaml::from_str(&metadata_string) {
+        Ok(header) => header,
+        Err(error) => {
+            problems.push(ModpackError::InvalidManifest(error.to_string()));
+            return None;
+        }
+    };
+
+    let mut invalid_version = false;
+    if let Some(Err(error)) = header.version.as_deref().map(Version::parse) {
+        problems.push(ModpackError::InvalidVersion {
+            key: "version",
+            error: error.to_string(),
+        });
+        invalid_version = true;
+    }
+    match header.hyperbeam_version.as_deref().map(VersionReq::parse) {
+        Some(Ok(required)) => {
+            let installed = Version::parse(self_update::VERSION).unwrap();
+            if !required.matches(&installed) {
+                problems.push(ModpackError::UnsupportedLauncherVersion {
+                    required,
+                    installed,
+                });
+            }
+        }
+        Some(Err(error)) => {
+            problems.push(ModpackError::InvalidVersion {
+                key: "hyperbeamVersion",
+                error: error.to_string(),
+            });
+            invalid_version = true;
+        }
+        None => {}
+    }
 
-        if metadata.target != "RTDX" {
-            return Err(Box::new(TargetError {}));
+    let expected_id = expected_id(path);
+    if let Some(id) = header.id {
+        if expected_id != id {
+            problems.push(ModpackError::IDMismatch {
+                id,
+                folder_name: expected_id.to_owned(),
+            });
         }
+    }
 
-        Ok(Modpack {
-            metadata,
-            path: path.to_owned(),
-            icon: None,
-        })
+    if let Some(target) = header.target {
+        if target != "RTDX" {
+            problems.push(ModpackError::WrongTarget(target));
+        }
     }
 
-    fn try_load_icon(&mut self) -> Result<NonNull<Texture2D>, Box<dyn Error>> {
-        let mut path = self.path.clone();
-        path.push("icon.png");
-        // TODO: error if the image is not actually a PNG
-        let image = image::io::Reader::open(path)?.decode()?.flipv();
-        let rgba_image = image.to_rgba8();
+    match serde_yaml::from_str::<ModpackMetadata>(&metadata_string) {
+        Ok(metadata) => {
+            for key in metadata.unknown_keys.keys() {
+                problems.push(ModpackError::UnknownKey(key.clone()));
+            }
+            Some(metadata)
+        }
+        Err(error) => {
+            // Invalid versions also fail the full parse, don't report them twice
+            if !invalid_version {
+                problems.push(ModpackError::InvalidManifest(error.to_string()));
+            }
+            None
+        }
+    }
+}
 
-        let expected_width = 250;
-        let expected_height = 250;
-        if image.width() as i32 != expected_width || image.height() as i32 != expected_height {
-            return Err(Box::new(ImageDimensionsError {
+fn check_image(
+    path: &Path,
+    file_name: &'static str,
+    expected_width: i32,
+    expected_height: i32,
+) -> Option<ModpackError> {
+    let dimensions = match modpack_files::read_file(path, file_name) {
+        Ok(Some(data)) => image::io::Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .map_err(image::ImageError::from)
+            .and_then(|reader| reader.into_dimensions()),
+        Ok(None) => return None,
+        Err(error) => Err(image::ImageError::from(error)),
+    };
+    match dimensions {
+        Ok((width, height))
+            if width as i32 != expected_width || height as i32 != expected_height =>
+        {
+            Some(ModpackError::ImageDimensions {
+                file_name,
                 expected_width,
                 expected_height,
-            }));
+                width: width as i32,
+                height: height as i32,
+            })
         }
+        Ok(_) => None,
+        Err(error) => Some(ModpackError::InvalidImage {
+            file_name,
+            error: error.to_string(),
+        }),
+    }
+}
 
-        Ok(texture_helpers::texture2d_from_bytes(
-            rgba_image.as_raw(),
+/// Returns the file names of the NROs in the plugins folder, sorted by name
+fn plugin_file_names(path: &Path) -> Vec<String> {
+    let mut names: Vec<String> = modpack_files::list_files(path, PLUGINS_DIR)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|name| name.rsplit('/').next())
+        .filter(|name| name.ends_with(".nro"))
+        .map(str::to_owned)
+        .collect();
+    names.sort();
+    names
+}
+
+/// Compares the `plugins` section of the manifest with the plugins folder.
+fn check_plugins(path: &Path, metadata: &ModpackMetadata) -> Vec<ModpackError> {
+    let entries = match &metadata.plugins {
+        Some(entries) => entries,
+        None => return Vec::new(),
+    };
+    let file_names = plugin_file_names(path);
+
+    let missing = entries
+        .iter()
+        .filter(|entry| !file_names.contains(&entry.name))
+        .map(|entry| ModpackError::MissingPlugin {
+            name: entry.name.clone(),
+            required: entry.is_required(),
+        });
+    let unlisted = file_names
+        .iter()
+        .filter(|name| !entries.iter().any(|entry| &entry.name == *name))
+        .map(|name| ModpackError::UnlistedPlugin(name.clone()));
+    missing.chain(unlisted).collect()
+}
+
+fn is_romfs_empty(path: &Path) -> bool {
+    !modpack_files::has_contents(path, "romfs").unwrap_or(false)
+}
+
+fn decode_image(path: &Path, file_name: &str) -> Result<image::DynamicImage, Box<dyn Error>> {
+    let data = modpack_files::read_file(path, file_name)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, file_name.to_owned()))?;
+    Ok(image::load_from_memory(&data)?)
+}
+
+fn load_image(
+    path: &Path,
+    file_name: &'static str,
+    expected_width: i32,
+    expected_height: i32,
+) -> Result<NonNull<Texture2D>, Box<dyn Error>> {
+    // TODO: error if the image is not actually a PNG
+    let image = decode_image(path, file_name)?.flipv();
+    let rgba_image = image.to_rgba8();
+
+    if image.width() as i32 != expected_width || image.height() as i32 != expected_height {
+        return Err(Box::new(ModpackError::ImageDimensions {
+            file_name,
             expected_width,
             expected_height,
-            false,
-            false,
-        ))
+            width: image.width() as i32,
+            height: image.height() as i32,
+        }));
+    }
+
+    Ok(texture_helpers::texture2d_from_bytes(
+        rgba_image.as_raw(),
+        expected_width,
+        expected_height,
+        false,
+        false,
+    ))
+}
+
+impl Modpack {
+    /// Validates the modpack folder or archive and collects all problems that were found.
+    /// Fails with all problems if any of them is an error.
+    pub fn new(path: &Path) -> Result<Modpack, Vec<ModpackError>> {
+        let mut problems = Vec::new();
+        let metadata = read_metadata(path, &mut problems);
+
+        problems.extend(check_image(path, ICON_FILE_NAME, ICON_WIDTH, ICON_HEIGHT));
+        problems.extend(check_image(
+            path,
+            SPLASH_FILE_NAME,
+            SPLASH_WIDTH,
+            SPLASH_HEIGHT,
+        ));
+        if is_romfs_empty(path) {
+            problems.push(ModpackError::EmptyRomfs);
+        }
+        if let Some(metadata) = &metadata {
+            problems.extend(check_plugins(path, metadata));
+        }
+
+        match metadata {
+            Some(metadata) if !has_errors(&problems) => Ok(Modpack {
+                metadata,
+                path: path.to_owned(),
+                icon: None,
+                warnings: problems,
+            }),
+            _ => Err(problems),
+        }
+    }
+
+    fn try_load_icon(&mut self) -> Result<NonNull<Texture2D>, Box<dyn Error>> {
+        load_image(&self.path, ICON_FILE_NAME, ICON_WIDTH, ICON_HEIGHT)
     }
 
     pub fn load_icon(&mut self) -> Result<NonNull<Texture2D>, &Box<dyn Error>> {
@@ -157,50 +465,239 @@ impl Modpack {
     }
 
     pub fn load_splash_image(&self) -> Result<NonNull<Texture2D>, Box<dyn Error>> {
-        let mut path = self.path.clone();
-        path.push("splash.png");
-        let image = image::io::Reader::open(path)?.decode()?.flipv();
-        let rgba_image = image.to_rgba8();
+        load_image(&self.path, SPLASH_FILE_NAME, SPLASH_WIDTH, SPLASH_HEIGHT)
+    }
 
-        let expected_width = 1280;
-        let expected_height = 720;
-        if image.width() as i32 != expected_width || image.height() as i32 != expected_height {
-            return Err(Box::new(ImageDimensionsError {
-                expected_width,
-                expected_height,
-            }));
-        }
+    /// Images in the `screenshots` folder, sorted by file name
+    pub fn screenshot_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = modpack_files::list_files(&self.path, "screenshots")
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|name| name.ends_with(".png"))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Loads a screenshot, which can have any size
+    pub fn load_screenshot(&self, name: &str) -> Result<NonNull<Texture2D>, Box<dyn Error>> {
+        let image = decode_image(&self.path, name)?.flipv();
+        let rgba_image = image.to_rgba8();
 
         Ok(texture_helpers::texture2d_from_bytes(
             rgba_image.as_raw(),
-            expected_width,
-            expected_height,
+            image.width() as i32,
+            image.height() as i32,
             false,
             false,
         ))
     }
 
-    pub fn load_plugins(&self) {
-        if let Ok(dir_contents) = fs::read_dir(self.path.join(Path::new("plugins"))) {
-            dir_contents
-                .filter_map(|f| f.ok())
-                .map(|f| f.path())
-                .filter(|f| f.is_file())
-                .filter(|f| f.extension() == Some(OsStr::new("nro")))
-                .for_each(|f| {
-                    let plugin_path = CString::new(f.to_str().unwrap()).unwrap();
-                    if !unsafe { add_plugin(plugin_path.as_ptr()) } {
-                        panic!("[hyperbeam-launcher] Failed to add plugin.");
-                    }
-                });
+    /// Returns the modpack's plugins in load order. Without a `plugins` section in the manifest,
+    /// all plugins are loaded in alphabetical order and are required.
+    pub fn plugins(&self) -> Vec<PluginEntry> {
+        match &self.metadata.plugins {
+            Some(entries) => entries.clone(),
+            None => plugin_file_names(&self.path)
+                .into_iter()
+                .map(|name| PluginEntry {
+                    name,
+                    optional: false,
+                    required: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Returns the path of one of the modpack's plugins. Plugins can only be loaded from files, so
+    /// plugins of modpack archives are extracted first.
+    fn plugin_path(&self, name: &str) -> Option<PathBuf> {
+        let file_name = format!("{}/{}", PLUGINS_DIR, name);
+        if !is_modpack_archive(&self.path) {
+            return Some(self.path.join(file_name));
         }
 
-        if unsafe { load_plugin_modules() } {
-            println!("[hyperbeam-launcher] Loaded plugin modules.");
+        let path = Path::new(ARCHIVE_CACHE_PATH)
+            .join(&self.metadata.id)
+            .join(&file_name);
+        match modpack_files::extract_file(&self.path, &file_name, &path) {
+            Ok(_) => Some(path),
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to extract plugin {}: {}",
+                    name, error
+                );
+                None
+            }
+        }
+    }
+
+    /// Checks the signature of one of the modpack's plugins.
+    fn check_plugin_signature(
+        &self,
+        name: &str,
+        trusted_keys: &[Vec<u8>],
+    ) -> io::Result<PluginTrust> {
+        let file_name = format!("{}/{}", PLUGINS_DIR, name);
+        let signature_name = format!("{}{}", file_name, plugin_signature::SIGNATURE_EXTENSION);
+        let plugin = modpack_files::read_file(&self.path, &file_name)?.unwrap_or_default();
+        let signature = modpack_files::read_file(&self.path, &signature_name)?;
+        Ok(plugin_signature::check_signature(
+            &plugin,
+            signature.as_deref(),
+            trusted_keys,
+        ))
+    }
+}
+
+/// Returns the modpacks to load for the selected modpack, highest priority first.
+/// See [`load_order::resolve_load_order`] for details.
+pub fn resolve_load_order<'a>(
+    modpack: &'a Modpack,
+    modpacks: &'a [ModpackLoadResult],
+) -> Result<Vec<&'a Modpack>, LoadOrderError> {
+    let available: Vec<&Modpack> = modpacks
+        .iter()
+        .filter_map(|load_result| match load_result {
+            ModpackLoadResult::Success(modpack) => Some(modpack),
+            _ => None,
+        })
+        .collect();
+    let available_metadata: Vec<&ModpackMetadata> =
+        available.iter().map(|modpack| &modpack.metadata).collect();
+
+    let order = load_order::resolve_load_order(&modpack.metadata, &available_metadata)?;
+    Ok(order
+        .iter()
+        .map(|metadata| {
+            *available
+                .iter()
+                .find(|modpack| modpack.metadata.id == metadata.id)
+                .unwrap_or(&modpack)
+        })
+        .collect())
+}
+
+/// Returns the enabled plugins of all layers with the layer they're loaded from, in load order.
+/// If multiple layers contain a plugin with the same file name, only the one from the highest
+/// priority layer is used.
+fn layer_plugins<'a>(layers: &[&'a Modpack]) -> Vec<(&'a Modpack, PluginEntry)> {
+    let mut plugins: Vec<(&Modpack, PluginEntry)> = Vec::new();
+    let mut seen_names: Vec<String> = Vec::new();
+    for layer in layers {
+        let config = ModpackConfig::load(&layer.path);
+        for plugin in layer.plugins() {
+            if seen_names.contains(&plugin.name) {
+                continue;
+            }
+            seen_names.push(plugin.name.clone());
+            if plugin.optional && !config.is_plugin_enabled(&plugin.name) {
+                println!(
+                    "[hyperbeam-launcher] Skipping disabled plugin {}/{}",
+                    layer.metadata.id, plugin.name
+                );
+                continue;
+            }
+            plugins.push((layer, plugin));
+        }
+    }
+    plugins
+}
+
+/// A plugin that needs to be confirmed by the user before it's loaded
+pub struct UntrustedPlugin {
+    pub modpack_id: String,
+    pub name: String,
+    pub trust: PluginTrust,
+}
+
+/// Returns the plugins of the layers that aren't signed with one of the trusted keys.
+pub fn untrusted_plugins(layers: &[&Modpack], trusted_keys: &[Vec<u8>]) -> Vec<UntrustedPlugin> {
+    layer_plugins(layers)
+        .into_iter()
+        .filter_map(|(layer, plugin)| {
+            let trust = layer
+                .check_plugin_signature(&plugin.name, trusted_keys)
+                .unwrap_or_else(|error| {
+                    eprintln!(
+                        "[hyperbeam-launcher] Failed to check signature of {}: {}",
+                        plugin.name, error
+                    );
+                    PluginTrust::Untrusted
+                });
+            match trust {
+                PluginTrust::Trusted => None,
+                trust => Some(UntrustedPlugin {
+                    modpack_id: layer.metadata.id.clone(),
+                    name: plugin.name,
+                    trust,
+                }),
+            }
+        })
+        .collect()
+}
+
+/// A plugin that isn't required and failed to load
+#[derive(Debug)]
+pub struct PluginFailure {
+    pub modpack_id: String,
+    pub name: String,
+}
+
+fn add_plugin_file(path: &Path) -> bool {
+    match path.to_str().and_then(|path| CString::new(path).ok()) {
+        Some(plugin_path) => unsafe { add_plugin(plugin_path.as_ptr()) },
+        None => false,
+    }
+}
+
+/// Loads the enabled plugins of all layers, see [`layer_plugins`]. Plugins that aren't required
+/// are skipped if they fail to load and are returned. The game can't be started if a required
+/// plugin fails to load, so this panics.
+pub fn load_plugins(layers: &[&Modpack]) -> Vec<PluginFailure> {
+    let mut failures = Vec::new();
+    let mut added_plugins = Vec::new();
+    for (layer, plugin) in layer_plugins(layers) {
+        let added = layer
+            .plugin_path(&plugin.name)
+            .map(|path| add_plugin_file(&path))
+            .unwrap_or(false);
+        if added {
+            added_plugins.push((layer, plugin));
+        } else if plugin.is_required() {
+            panic!(
+                "[hyperbeam-launcher] Failed to add required plugin {}/{}.",
+                layer.metadata.id, plugin.name
+            );
         } else {
-            panic!("Failed to load plugin modules!");
+            eprintln!(
+                "[hyperbeam-launcher] Failed to add plugin {}/{}, skipping it.",
+                layer.metadata.id, plugin.name
+            );
+            failures.push(PluginFailure {
+                modpack_id: layer.metadata.id.clone(),
+                name: plugin.name,
+            });
         }
     }
+
+    if unsafe { load_plugin_modules() } {
+        println!("[hyperbeam-launcher] Loaded plugin modules.");
+    } else if added_plugins.iter().any(|(_, plugin)| plugin.is_required()) {
+        panic!("Failed to load plugin modules!");
+    } else {
+        // It's unknown which plugin failed, so all of them are reported
+        eprintln!("[hyperbeam-launcher] Failed to load plugin modules.");
+        failures.extend(
+            added_plugins
+                .into_iter()
+                .map(|(layer, plugin)| PluginFailure {
+                    modpack_id: layer.metadata.id.clone(),
+                    name: plugin.name,
+                }),
+        );
+    }
+    failures
 }
 
 pub fn load_all_modpacks() -> Result<Vec<ModpackLoadResult>, Box<dyn Error>> {
@@ -208,14 +705,14 @@ pub fn load_all_modpacks() -> Result<Vec<ModpackLoadResult>, Box<dyn Error>> {
     for dir in fs::read_dir(MODPACK_BASE_PATH)? {
         let dir = dir?;
         let mut path = dir.path();
-        if path.is_dir() {
+        if path.is_dir() || is_modpack_archive(&path) {
             match Modpack::new(&path) {
                 Ok(modpack) => {
                     println!("Loaded modpack data: {:?}", modpack);
                     modpacks.push(ModpackLoadResult::Success(modpack));
                 }
-                Err(error) => {
-                    let invalid_modpack = InvalidModpack { error, path };
+                Err(problems) => {
+                    let invalid_modpack = InvalidModpack { problems, path };
                     eprintln!("Failed to load modpack data: {:?}", invalid_modpack);
                     modpacks.push(ModpackLoadResult::Invalid(invalid_modpack));
                 }
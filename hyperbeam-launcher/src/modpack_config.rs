@@ -0,0 +1,78 @@
+use hyperbeam_rtdx::modpack::is_modpack_archive;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the user's settings for a modpack, stored in the modpack folder. It's kept when the
+/// modpack is upgraded.
+pub const MODPACK_CONFIG_FILE_NAME: &str = "config.yaml";
+
+/// The user's settings for a modpack
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModpackConfig {
+    /// Names of optional plugins that shouldn't be loaded
+    #[serde(default)]
+    pub disabled_plugins: Vec<String>,
+}
+
+/// Returns the path of a modpack's config. Modpack archives can't be modified, so their config is
+/// stored next to the archive, e.g. `foo.config.yaml` for `foo.hbpack`.
+pub fn config_path(modpack_path: &Path) -> PathBuf {
+    if is_modpack_archive(modpack_path) {
+        modpack_path.with_extension(MODPACK_CONFIG_FILE_NAME)
+    } else {
+        modpack_path.join(MODPACK_CONFIG_FILE_NAME)
+    }
+}
+
+impl ModpackConfig {
+    /// Reads the config of a modpack. Returns the default config if the modpack doesn't have a
+    /// config yet or it can't be read.
+    pub fn load(modpack_path: &Path) -> ModpackConfig {
+        let path = config_path(modpack_path);
+        let config_string = match fs::read_to_string(&path) {
+            Ok(config_string) => config_string,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return ModpackConfig::default()
+            }
+            Err(error) => {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to read {}: {}",
+                    path.display(),
+                    error
+                );
+                return ModpackConfig::default();
+            }
+        };
+        serde_yaml::from_str(&config_string).unwrap_or_else(|error| {
+            eprintln!(
+                "[hyperbeam-launcher] Invalid modpack config {}: {}",
+                path.display(),
+                error
+            );
+            ModpackConfig::default()
+        })
+    }
+
+    pub fn save(&self, modpack_path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(config_path(modpack_path), serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn is_plugin_enabled(&self, name: &str) -> bool {
+        !self
+            .disabled_plugins
+            .iter()
+            .any(|disabled| disabled == name)
+    }
+
+    pub fn set_plugin_enabled(&mut self, name: &str, enabled: bool) {
+        self.disabled_plugins.retain(|disabled| disabled != name);
+        if !enabled {
+            self.disabled_plugins.push(name.to_owned());
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use hyperbeam_rtdx::archive::to_io_error;
+use hyperbeam_rtdx::modpack::is_modpack_archive;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+// Modpacks are either folders or zip archives of a modpack folder. These functions access
+// files in both kinds of modpacks by their path relative to the modpack root, e.g. `icon.png`.
+
+pub fn open_archive(archive_path: &Path) -> io::Result<ZipArchive<File>> {
+    ZipArchive::new(File::open(archive_path)?).map_err(to_io_error)
+}
+
+/// Reads a file of a modpack. Returns `None` if the file doesn't exist.
+pub fn read_file(modpack_path: &Path, name: &str) -> io::Result<Option<Vec<u8>>> {
+    if is_modpack_archive(modpack_path) {
+        let mut archive = open_archive(modpack_path)?;
+        let mut file = match archive.by_name(name) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(error) => return Err(to_io_error(error)),
+        };
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        Ok(Some(data))
+    } else {
+        match fs::read(modpack_path.join(name)) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Returns the paths of the files directly inside a directory of a modpack, e.g.
+/// `screenshots/1.png` for `screenshots`. Returns an empty list if the directory doesn't exist.
+pub fn list_files(modpack_path: &Path, dir: &str) -> io::Result<Vec<String>> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    if is_modpack_archive(modpack_path) {
+        Ok(open_archive(modpack_path)?
+            .file_names()
+            .filter_map(|name| name.strip_prefix(&prefix))
+            .filter(|name| !name.is_empty() && !name.contains('/'))
+            .map(|name| format!("{}{}", prefix, name))
+            .collect())
+    } else {
+        let dir_contents = match fs::read_dir(modpack_path.join(dir)) {
+            Ok(dir_contents) => dir_contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        Ok(dir_contents
+            .filter_map(|f| f.ok())
+            .filter(|f| f.path().is_file())
+            .filter_map(|f| {
+                f.file_name()
+                    .to_str()
+                    .map(|name| format!("{}{}", prefix, name))
+            })
+            .collect())
+    }
+}
+
+/// Checks whether a directory of a modpack contains anything.
+pub fn has_contents(modpack_path: &Path, dir: &str) -> io::Result<bool> {
+    let prefix = format!("{}/", dir.trim_end_matches('/'));
+    if is_modpack_archive(modpack_path) {
+        Ok(open_archive(modpack_path)?
+            .file_names()
+            .any(|name| name.len() > prefix.len() && name.starts_with(&prefix)))
+    } else {
+        match fs::read_dir(modpack_path.join(dir)) {
+            Ok(mut dir_contents) => Ok(dir_contents.next().is_some()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// Copies a file out of a modpack archive.
+pub fn extract_file(archive_path: &Path, name: &str, destination: &Path) -> io::Result<()> {
+    let mut archive = open_archive(archive_path)?;
+    let mut file = archive.by_name(name).map_err(to_io_error)?;
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    io::copy(&mut file, &mut File::create(destination)?)?;
+    Ok(())
+}
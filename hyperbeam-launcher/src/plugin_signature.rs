@@ -0,0 +1,74 @@
+use ring::signature::{UnparsedPublicKey, ED25519};
+use std::fmt::{self, Display, Formatter};
+
+/// Appended to the plugin file name to get the name of its signature file, e.g.
+/// `plugins/foo.nro.sig`. The signature file contains the raw 64 byte ed25519 signature of the
+/// plugin, which can be created with `tools/sign_plugin.sh`.
+pub const SIGNATURE_EXTENSION: &str = ".sig";
+
+const PUBLIC_KEY_LENGTH: usize = 32;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PluginTrust {
+    /// The plugin is signed with one of the trusted keys
+    Trusted,
+    /// The plugin doesn't have a signature file
+    Unsigned,
+    /// The signature doesn't match the plugin with any of the trusted keys
+    Untrusted,
+}
+
+impl Display for PluginTrust {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            PluginTrust::Trusted => write!(f, "trusted"),
+            PluginTrust::Unsigned => write!(f, "not signed"),
+            PluginTrust::Untrusted => write!(f, "not signed by a trusted author"),
+        }
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Decodes the hex encoded public keys from the config. Invalid keys are skipped.
+pub fn parse_trusted_keys(keys: &[String]) -> Vec<Vec<u8>> {
+    keys.iter()
+        .filter_map(|key| match decode_hex(key.trim()) {
+            Some(key) if key.len() == PUBLIC_KEY_LENGTH => Some(key),
+            _ => {
+                eprintln!("[hyperbeam-launcher] Ignoring invalid trusted key: {}", key);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Checks whether a plugin is signed by one of the trusted keys.
+pub fn check_signature(
+    plugin: &[u8],
+    signature: Option<&[u8]>,
+    trusted_keys: &[Vec<u8>],
+) -> PluginTrust {
+    let signature = match signature {
+        Some(signature) => signature,
+        None => return PluginTrust::Unsigned,
+    };
+    let trusted = trusted_keys.iter().any(|key| {
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(plugin, signature)
+            .is_ok()
+    });
+    if trusted {
+        PluginTrust::Trusted
+    } else {
+        PluginTrust::Untrusted
+    }
+}
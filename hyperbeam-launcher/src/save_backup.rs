@@ -0,0 +1,287 @@
+use crate::fs_helpers::{copy_dir_all, replace_dir_with_copy};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const BACKUP_BASE_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/backups";
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SaveBackup {
+    /// Name of the backup directory, which is the time the backup was created at (UTC)
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl SaveBackup {
+    /// Creation time formatted for display, e.g. `2021-10-18 14:03:10 UTC`
+    pub fn display_name(&self) -> String {
+        let mut parts = self.name.splitn(3, '_');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(date), Some(time), counter) => format!(
+                "{} {} UTC{}",
+                date,
+                time.replace('-', ":"),
+                counter.map(|c| format!(" ({})", c)).unwrap_or_default()
+            ),
+            _ => self.name.clone(),
+        }
+    }
+}
+
+/// Rotating set of timestamped backups of one save data directory.
+#[derive(Debug)]
+pub struct BackupManager {
+    backup_path: PathBuf,
+    max_backups: usize,
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD_HH-MM-SS`, which sorts chronologically.
+pub fn format_timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Civil date from days since epoch, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+impl BackupManager {
+    pub fn new<P: Into<PathBuf>>(backup_path: P, max_backups: usize) -> BackupManager {
+        BackupManager {
+            backup_path: backup_path.into(),
+            max_backups,
+        }
+    }
+
+    /// Backups of the base game's save data or of a modpack's separate save data
+    pub fn for_save_slot(slot: &str, max_backups: usize) -> BackupManager {
+        BackupManager::new(Path::new(BACKUP_BASE_PATH).join(slot), max_backups)
+    }
+
+    /// Returns all backups, newest first.
+    pub fn list(&self) -> io::Result<Vec<SaveBackup>> {
+        if !self.backup_path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(&self.backup_path)? {
+            let entry = entry?;
+            if !entry.path().is_dir() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                backups.push(SaveBackup {
+                    name: name.to_owned(),
+                    path: entry.path(),
+                });
+            }
+        }
+        backups.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(backups)
+    }
+
+    /// Copies the save data into a new backup and deletes the oldest backups that exceed the
+    /// maximum count. `timestamp` is the current time in seconds since the Unix epoch.
+    pub fn create(&self, save_path: &Path, timestamp: u64) -> io::Result<SaveBackup> {
+        // Multiple backups in the same second get a counter, which must be higher than all
+        // existing ones so that the new backup isn't rotated out right away
+        let base_name = format_timestamp(timestamp);
+        let highest_counter = self
+            .list()?
+            .iter()
+            .filter_map(|backup| backup.name.strip_prefix(&base_name))
+            .map(|suffix| suffix.trim_start_matches('_').parse::<u32>().unwrap_or(1))
+            .max();
+        let name = match highest_counter {
+            Some(counter) => format!("{}_{}", base_name, counter + 1),
+            None => base_name,
+        };
+
+        let backup = SaveBackup {
+            path: self.backup_path.join(&name),
+            name,
+        };
+        if let Err(error) = copy_dir_all(save_path, &backup.path) {
+            // Don't leave incomplete backups around
+            let _ = fs::remove_dir_all(&backup.path);
+            return Err(error);
+        }
+
+        self.rotate()?;
+        Ok(backup)
+    }
+
+    /// Backs up the save data with the current system time, unless backups are disabled.
+    pub fn create_now(&self, save_path: &Path) -> io::Result<Option<SaveBackup>> {
+        if self.max_backups == 0 {
+            return Ok(None);
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+        self.create(save_path, timestamp).map(Some)
+    }
+
+    /// Deletes the oldest backups until at most the maximum count remains.
+    pub fn rotate(&self) -> io::Result<()> {
+        for backup in self.list()?.iter().skip(self.max_backups) {
+            fs::remove_dir_all(&backup.path)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the contents of the save data directory with the backup. The save data is left
+    /// untouched if the backup can't be copied.
+    pub fn restore(&self, backup: &SaveBackup, save_path: &Path) -> io::Result<()> {
+        replace_dir_with_copy(&backup.path, save_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_save(path: &Path, contents: &str) {
+        fs::create_dir_all(path).unwrap();
+        fs::write(path.join("save.bin"), contents).unwrap();
+    }
+
+    fn backup_names(manager: &BackupManager) -> Vec<String> {
+        manager
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect()
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(format_timestamp(0), "1970-01-01_00-00-00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29_00-00-00");
+        assert_eq!(format_timestamp(1_634_565_790), "2021-10-18_14-03-10");
+    }
+
+    #[test]
+    fn display_names() {
+        let backup = |name: &str| SaveBackup {
+            name: name.to_owned(),
+            path: PathBuf::new(),
+        };
+        assert_eq!(
+            backup("2021-10-18_14-03-10").display_name(),
+            "2021-10-18 14:03:10 UTC"
+        );
+        assert_eq!(
+            backup("2021-10-18_14-03-10_2").display_name(),
+            "2021-10-18 14:03:10 UTC (2)"
+        );
+        assert_eq!(backup("manual").display_name(), "manual");
+    }
+
+    #[test]
+    fn rotation_keeps_newest_backups() {
+        let root = TempDir::new().unwrap();
+        let save_path = root.path().join("save");
+        create_save(&save_path, "save");
+        let manager = BackupManager::new(root.path().join("backups"), 2);
+
+        for timestamp in &[30, 10, 20] {
+            manager.create(&save_path, *timestamp).unwrap();
+        }
+        assert_eq!(
+            backup_names(&manager),
+            ["1970-01-01_00-00-30", "1970-01-01_00-00-20"]
+        );
+    }
+
+    #[test]
+    fn backups_in_same_second_get_counter() {
+        let root = TempDir::new().unwrap();
+        let save_path = root.path().join("save");
+        create_save(&save_path, "save");
+        let manager = BackupManager::new(root.path().join("backups"), 2);
+
+        for _ in 0..3 {
+            manager.create(&save_path, 0).unwrap();
+        }
+        assert_eq!(
+            backup_names(&manager),
+            ["1970-01-01_00-00-00_3", "1970-01-01_00-00-00_2"]
+        );
+    }
+
+    #[test]
+    fn disabled_backups() {
+        let root = TempDir::new().unwrap();
+        let save_path = root.path().join("save");
+        create_save(&save_path, "save");
+        let manager = BackupManager::new(root.path().join("backups"), 0);
+
+        assert_eq!(manager.create_now(&save_path).unwrap(), None);
+        assert!(backup_names(&manager).is_empty());
+    }
+
+    #[test]
+    fn restore_replaces_save_data() {
+        let root = TempDir::new().unwrap();
+        let save_path = root.path().join("save");
+        create_save(&save_path, "old");
+        let manager = BackupManager::new(root.path().join("backups"), 5);
+        let backup = manager.create(&save_path, 0).unwrap();
+
+        fs::remove_dir_all(&save_path).unwrap();
+        create_save(&save_path, "new");
+        fs::write(save_path.join("extra.bin"), "extra").unwrap();
+        manager.restore(&backup, &save_path).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(save_path.join("save.bin")).unwrap(),
+            "old"
+        );
+        assert!(!save_path.join("extra.bin").exists());
+        assert_eq!(fs::read_dir(root.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn failed_restore_keeps_save_data() {
+        let root = TempDir::new().unwrap();
+        let save_path = root.path().join("save");
+        create_save(&save_path, "current");
+        let manager = BackupManager::new(root.path().join("backups"), 5);
+        let missing_backup = SaveBackup {
+            name: "missing".to_owned(),
+            path: root.path().join("backups/missing"),
+        };
+
+        assert!(manager.restore(&missing_backup, &save_path).is_err());
+        assert_eq!(
+            fs::read_to_string(save_path.join("save.bin")).unwrap(),
+            "current"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::fs_helpers::replace_dir_with_copy;
+use hyperbeam_rtdx::modpack::ModpackMetadata;
+use skyline::nn;
+use skyline::{hook, install_hook, install_hooks};
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::io;
+use std::os::raw::c_char;
+use std::path::{Path, PathBuf};
+
+/// Mount point of the game's save data
+pub const SAVE_MOUNT_PATH: &str = "save:/";
+const SAVE_MOUNT_NAME: &str = "save";
+pub const SAVE_BASE_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/saves";
+
+/// Directory of the save data that is used instead of the game's save data while a modpack is loaded
+static mut SAVE_REDIRECT_PATH: Option<PathBuf> = None;
+
+pub fn modpack_save_path(id: &str) -> PathBuf {
+    PathBuf::from(SAVE_BASE_PATH).join(id)
+}
+
+/// Returns the directory containing the save data that is used with a modpack, or the base
+/// game's save data if `modpack` is `None`.
+pub fn save_path_for(modpack: Option<&ModpackMetadata>) -> PathBuf {
+    match modpack {
+        Some(modpack) if !modpack.shared_save => modpack_save_path(&modpack.id),
+        _ => PathBuf::from(SAVE_MOUNT_PATH),
+    }
+}
+
+/// Maps a path in the game's save data to the same path in `save_path`.
+/// Returns `None` for paths outside of the save data.
+pub fn redirect_save_path(path: &str, save_path: &Path) -> Option<PathBuf> {
+    path.strip_prefix(SAVE_MOUNT_PATH)
+        .map(|relative_path| save_path.join(relative_path.trim_start_matches('/')))
+}
+
+pub fn commit_save_data() -> io::Result<()> {
+    let mount_name = CString::new(SAVE_MOUNT_NAME).unwrap();
+    let result = unsafe { nn::fs::CommitSaveData(mount_name.as_ptr() as _) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("Failed to commit save data (0x{:x})", result),
+        ))
+    }
+}
+
+/// Replaces the modpack's save data with a copy of the base game's save data.
+pub fn copy_vanilla_save_to_modpack(id: &str) -> io::Result<()> {
+    replace_dir_with_copy(Path::new(SAVE_MOUNT_PATH), &modpack_save_path(id))
+}
+
+/// Replaces the base game's save data with a copy of the modpack's save data.
+pub fn copy_modpack_save_to_vanilla(id: &str) -> io::Result<()> {
+    let save_path = modpack_save_path(id);
+    if !save_path.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "The modpack doesn't have any save data yet",
+        ));
+    }
+    replace_dir_with_copy(&save_path, Path::new(SAVE_MOUNT_PATH))?;
+    commit_save_data()
+}
+
+/// Redirects all save data accesses of the game to the modpack's save directory.
+pub fn isolate_save_data(id: &str) -> io::Result<()> {
+    let save_path = modpack_save_path(id);
+    fs::create_dir_all(&save_path)?;
+    println!(
+        "[hyperbeam-launcher] Redirecting save data to {}",
+        save_path.display()
+    );
+    unsafe {
+        SAVE_REDIRECT_PATH = Some(save_path);
+    }
+    install_hooks!(
+        hook_open_file,
+        hook_create_file,
+        hook_delete_file,
+        hook_rename_file,
+        hook_get_entry_type,
+        hook_create_directory,
+        hook_open_directory,
+        hook_delete_directory,
+        hook_delete_directory_recursively,
+        hook_rename_directory
+    );
+    Ok(())
+}
+
+unsafe fn redirected_path(path: *const c_char) -> Option<CString> {
+    let save_path = SAVE_REDIRECT_PATH.as_ref()?;
+    let path = CStr::from_ptr(path).to_str().ok()?;
+    redirect_save_path(path, save_path)
+        .and_then(|redirected| CString::new(redirected.to_str()?).ok())
+}
+
+#[hook(replace = nn::fs::OpenFile)]
+unsafe fn hook_open_file(handle: *mut nn::fs::FileHandle, path: *const c_char, mode: i32) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(handle, redirected.as_ptr(), mode),
+        None => call_original!(handle, path, mode),
+    }
+}
+
+#[hook(replace = nn::fs::CreateFile)]
+unsafe fn hook_create_file(path: *const c_char, size: i64) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(redirected.as_ptr(), size),
+        None => call_original!(path, size),
+    }
+}
+
+#[hook(replace = nn::fs::DeleteFile)]
+unsafe fn hook_delete_file(path: *const c_char) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(redirected.as_ptr()),
+        None => call_original!(path),
+    }
+}
+
+/// Returns the redirected path if there is one, otherwise `path` itself
+fn path_ptr(redirected: &Option<CString>, path: *const c_char) -> *const c_char {
+    redirected
+        .as_ref()
+        .map(|redirected| redirected.as_ptr())
+        .unwrap_or(path)
+}
+
+#[hook(replace = nn::fs::RenameFile)]
+unsafe fn hook_rename_file(current_path: *const c_char, new_path: *const c_char) -> i32 {
+    let redirected_current_path = redirected_path(current_path);
+    let redirected_new_path = redirected_path(new_path);
+    call_original!(
+        path_ptr(&redirected_current_path, current_path),
+        path_ptr(&redirected_new_path, new_path)
+    )
+}
+
+#[hook(replace = nn::fs::GetEntryType)]
+unsafe fn hook_get_entry_type(
+    entry_type: *mut nn::fs::DirectoryEntryType,
+    path: *const c_char,
+) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(entry_type, redirected.as_ptr()),
+        None => call_original!(entry_type, path),
+    }
+}
+
+#[hook(replace = nn::fs::CreateDirectory)]
+unsafe fn hook_create_directory(path: *const c_char) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(redirected.as_ptr()),
+        None => call_original!(path),
+    }
+}
+
+#[hook(replace = nn::fs::OpenDirectory)]
+unsafe fn hook_open_directory(
+    handle: *mut nn::fs::DirectoryHandle,
+    path: *const c_char,
+    mode: i32,
+) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(handle, redirected.as_ptr(), mode),
+        None => call_original!(handle, path, mode),
+    }
+}
+
+#[hook(replace = nn::fs::DeleteDirectory)]
+unsafe fn hook_delete_directory(path: *const c_char) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(redirected.as_ptr()),
+        None => call_original!(path),
+    }
+}
+
+#[hook(replace = nn::fs::DeleteDirectoryRecursively)]
+unsafe fn hook_delete_directory_recursively(path: *const c_char) -> i32 {
+    match redirected_path(path) {
+        Some(redirected) => call_original!(redirected.as_ptr()),
+        None => call_original!(path),
+    }
+}
+
+#[hook(replace = nn::fs::RenameDirectory)]
+unsafe fn hook_rename_directory(current_path: *const c_char, new_path: *const c_char) -> i32 {
+    let redirected_current_path = redirected_path(current_path);
+    let redirected_new_path = redirected_path(new_path);
+    call_original!(
+        path_ptr(&redirected_current_path, current_path),
+        path_ptr(&redirected_new_path, new_path)
+    )
+}
@@ -1,41 +1,230 @@
-use hyperbeam_rtdx::serialization;
+use crate::checksums;
+use crate::config::{Config, UpdateChannel};
+use crate::plugin_signature::{self, PluginTrust};
+use crate::update_installer;
 use semver::Version;
 use serde::Deserialize;
-use std::io::{self, Cursor};
-use std::fs::{self, File};
 use std::error;
 use std::fmt::{self, Display, Formatter};
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Mutex;
 use std::thread;
-use zip::ZipArchive;
 
-const LATEST_RELEASE_URL: &str =
-    "https://api.github.com/repos/tech-ticks/hyperbeam-rs/releases/latest";
-const UPDATE_BASE_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs";
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Release feed used if the config doesn't set `updateFeed`
+pub const DEFAULT_UPDATE_FEED: &str =
+    "https://api.github.com/repos/tech-ticks/hyperbeam-rs/releases";
+/// Name of the launcher asset if the config doesn't set `updateAssetPattern`. Updates are zip
+/// archives of the launcher and its data, with paths relative to the game's romfs folder.
+pub const DEFAULT_ASSET_PATTERN: &str = "hyperbeam*.zip";
+/// Appended to the name of the launcher asset to get the asset with its SHA-256 hash in the
+/// format of `sha256sum`
+pub const CHECKSUM_EXTENSION: &str = ".sha256";
+/// Updates are downloaded here, so that interrupted downloads can be resumed
+const DOWNLOAD_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/download";
+/// Versions the user chose to skip in the launcher, one per line
+const SKIPPED_VERSIONS_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/skipped_versions";
+/// Downloaded bytes are written to the file and reported in chunks of this size
+const DOWNLOAD_CHUNK_SIZE: usize = 0x10000;
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+lazy_static::lazy_static! {
+    /// Downloads that update threads are writing to. A thread keeps running after the launcher
+    /// gave up on it, e.g. after a timeout, until the download receives data or fails.
+    static ref ACTIVE_DOWNLOADS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+}
 
 #[derive(Deserialize, Debug)]
-struct GitHubReleaseAsset {
-    name: String,
-    size: u64,
-    browser_download_url: String,
+pub struct GitHubReleaseAsset {
+    pub name: String,
+    pub size: u64,
+    pub browser_download_url: String,
 }
 
 #[derive(Deserialize, Debug)]
-struct GitHubRelease {
-    name: String,
-    #[serde(deserialize_with = "serialization::from_semver")]
-    tag_name: Version,
-    body: String,
-    assets: Vec<GitHubReleaseAsset>,
+pub struct GitHubRelease {
+    /// Title of the release, `null` for untitled releases
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Version of the release, with an optional `v` prefix
+    pub tag_name: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    /// Release notes
+    #[serde(default)]
+    pub body: Option<String>,
+    pub assets: Vec<GitHubReleaseAsset>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+impl GitHubRelease {
+    /// Returns the version in the tag name, or `None` if it isn't a semantic version.
+    pub fn version(&self) -> Option<Version> {
+        Version::parse(self.tag_name.trim_start_matches('v')).ok()
+    }
+
+    /// Returns the first asset whose name matches the pattern, see [`matches_pattern`].
+    pub fn launcher_asset(&self, asset_pattern: &str) -> Option<&GitHubReleaseAsset> {
+        self.assets
+            .iter()
+            .find(|asset| matches_pattern(&asset.name, asset_pattern))
+    }
+
+    fn asset_url(&self, name: &str) -> Option<String> {
+        self.assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+    }
+
+    fn is_in_channel(&self, channel: UpdateChannel) -> bool {
+        match channel {
+            UpdateChannel::Stable => !self.prerelease,
+            UpdateChannel::Prerelease => true,
+        }
+    }
+}
+
+/// A release in a static update manifest, for update feeds that aren't hosted on GitHub:
+///
+/// ```json
+/// {
+///   "releases": [
+///     {
+///       "version": "0.3.0",
+///       "prerelease": false,
+///       "notes": "Release notes",
+///       "assets": [
+///         { "name": "hyperbeam.zip", "size": 1234, "url": "https://example.com/hyperbeam.zip" }
+///       ]
+///     }
+///   ]
+/// }
+/// ```
+#[derive(Deserialize, Debug)]
+pub struct ManifestRelease {
+    pub version: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub notes: String,
+    pub assets: Vec<ManifestAsset>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ManifestAsset {
+    pub name: String,
+    #[serde(default)]
+    pub size: u64,
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateManifest {
+    pub releases: Vec<ManifestRelease>,
+}
+
+impl From<ManifestRelease> for GitHubRelease {
+    fn from(release: ManifestRelease) -> GitHubRelease {
+        GitHubRelease {
+            name: Some(release.version.clone()),
+            tag_name: release.version,
+            prerelease: release.prerelease,
+            draft: false,
+            body: Some(release.notes),
+            assets: release
+                .assets
+                .into_iter()
+                .map(|asset| GitHubReleaseAsset {
+                    name: asset.name,
+                    size: asset.size,
+                    browser_download_url: asset.url,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Response of an update feed
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ReleaseFeed {
+    /// The GitHub API's list of releases
+    GitHubReleases(Vec<GitHubRelease>),
+    /// A single GitHub release, e.g. from `releases/latest`
+    GitHubRelease(GitHubRelease),
+    Manifest(UpdateManifest),
+}
+
+impl ReleaseFeed {
+    pub fn into_releases(self) -> Vec<GitHubRelease> {
+        match self {
+            ReleaseFeed::GitHubReleases(releases) => releases,
+            ReleaseFeed::GitHubRelease(release) => vec![release],
+            ReleaseFeed::Manifest(manifest) => manifest
+                .releases
+                .into_iter()
+                .map(GitHubRelease::from)
+                .collect(),
+        }
+    }
+}
+
+/// Where updates are looked up and which of them are offered
+#[derive(Debug, Clone)]
+pub struct UpdateSettings {
+    /// URL of the GitHub releases API of a repository or of an [`UpdateManifest`]
+    pub feed_url: String,
+    /// Name of the release asset that contains the launcher, see [`matches_pattern`]
+    pub asset_pattern: String,
+    pub channel: UpdateChannel,
+    /// Versions that aren't offered
+    pub skipped_versions: Vec<Version>,
+    /// ed25519 public keys that sign updates
+    pub public_keys: Vec<Vec<u8>>,
+}
+
+impl UpdateSettings {
+    /// Reads the settings from the config. Versions skipped in the launcher are added to
+    /// `skipVersion`.
+    pub fn from_config(config: &Config) -> UpdateSettings {
+        let mut skipped_versions = skipped_versions();
+        skipped_versions.extend(config.skipped_version());
+        UpdateSettings {
+            feed_url: config
+                .update_feed
+                .clone()
+                .unwrap_or_else(|| DEFAULT_UPDATE_FEED.to_owned()),
+            asset_pattern: config
+                .update_asset_pattern
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ASSET_PATTERN.to_owned()),
+            channel: config.update_channel,
+            skipped_versions,
+            public_keys: plugin_signature::parse_trusted_keys(&config.update_keys),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Update {
     pub version: Version,
     pub download: String,
+    /// Size of the download in bytes, 0 if unknown
+    pub size: u64,
+    /// Release notes
+    pub notes: String,
+    /// URL of the SHA-256 hash of the download
+    pub checksum: Option<String>,
+    /// URL of the ed25519 signature of the download's SHA-256 digest, which can be created with
+    /// `tools/sign_update.sh`
+    pub signature: Option<String>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -44,17 +233,33 @@ pub enum UpdateCheckResult {
     UpdateAvailable(Update),
 }
 
-pub struct ProgressPercentage(f32);
+#[derive(Debug, Copy, Clone)]
+pub struct DownloadProgress {
+    pub downloaded: u64,
+    /// Size of the download, if the server or the release reported it
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    /// Returns the downloaded fraction between 0 and 1.
+    pub fn fraction(&self) -> Option<f32> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.downloaded as f32 / total as f32).min(1.0))
+    }
+}
 
 pub enum UpdateProgress {
-    Downloading(ProgressPercentage),
+    Downloading(DownloadProgress),
     Installing,
     Finished,
     Err(Error),
 }
 
-#[derive(Debug)]
-struct NoReleaseAssetError;
+#[derive(Debug, Eq, PartialEq)]
+pub struct NoReleaseAssetError {
+    pub version: Version,
+}
 
 impl error::Error for NoReleaseAssetError {}
 
@@ -64,45 +269,152 @@ type Error = Box<dyn error::Error + Send + Sync>;
 
 impl Display for NoReleaseAssetError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "No release asset found")
+        write!(f, "No release asset found for version {}", self.version)
+    }
+}
+
+#[derive(Debug)]
+pub struct DownloadError {
+    pub status_code: i32,
+}
+
+impl error::Error for DownloadError {}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "Download failed with HTTP status {}", self.status_code)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UpdateVerificationError {
+    /// The release has neither a checksum nor a signature that can be checked
+    Unverified,
+    InvalidChecksum,
+    ChecksumMismatch,
+    InvalidSignature,
+}
+
+impl error::Error for UpdateVerificationError {}
+
+impl Display for UpdateVerificationError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UpdateVerificationError::Unverified => {
+                write!(f, "The update has no checksum or signature")
+            }
+            UpdateVerificationError::InvalidChecksum => write!(f, "The update checksum is invalid"),
+            UpdateVerificationError::ChecksumMismatch => {
+                write!(f, "The download doesn't match the update checksum")
+            }
+            UpdateVerificationError::InvalidSignature => {
+                write!(f, "The update isn't signed by a trusted key")
+            }
+        }
+    }
+}
+
+fn skipped_versions() -> Vec<Version> {
+    fs::read_to_string(SKIPPED_VERSIONS_PATH)
+        .map(|versions| {
+            versions
+                .lines()
+                .filter_map(|version| Version::parse(version.trim()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Stops offering a version as an update.
+pub fn skip_version(version: &Version) -> io::Result<()> {
+    let path = Path::new(SKIPPED_VERSIONS_PATH);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", version)
+}
+
+/// Checks whether a file name matches a pattern, in which `*` matches any number of characters.
+pub fn matches_pattern(name: &str, pattern: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let prefix = parts.next().unwrap_or_default();
+    let mut rest = match name.strip_prefix(prefix) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let suffix = match parts.pop() {
+        Some(suffix) => suffix,
+        // There is no wildcard
+        None => return rest.is_empty(),
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
     }
+    rest.len() >= suffix.len() && rest.ends_with(suffix)
 }
 
-fn get_latest_release() -> Result<GitHubRelease, Error> {
-    Ok(minreq::get(LATEST_RELEASE_URL)
+fn get_releases(feed_url: &str) -> Result<Vec<GitHubRelease>, Error> {
+    let feed: ReleaseFeed = minreq::get(feed_url)
         .with_header("User-Agent", "hyperbeam-launcher")
         .with_timeout(10)
         .send()?
-        .json()?)
+        .json()?;
+    Ok(feed.into_releases())
+}
+
+/// Decides which release to update to. The newest release in the update channel is offered if
+/// it's newer than the current version and wasn't skipped. Drafts and releases whose tag isn't a
+/// semantic version are ignored.
+pub fn find_update(
+    releases: &[GitHubRelease],
+    current_version: &Version,
+    settings: &UpdateSettings,
+) -> Result<UpdateCheckResult, NoReleaseAssetError> {
+    let newest_release = releases
+        .iter()
+        .filter(|release| !release.draft && release.is_in_channel(settings.channel))
+        .filter_map(|release| release.version().map(|version| (version, release)))
+        .filter(|(version, _)| {
+            version > current_version && !settings.skipped_versions.contains(version)
+        })
+        .max_by(|(version_a, _), (version_b, _)| version_a.cmp(version_b));
+
+    match newest_release {
+        Some((version, release)) => match release.launcher_asset(&settings.asset_pattern) {
+            Some(asset) => Ok(UpdateCheckResult::UpdateAvailable(Update {
+                version,
+                download: asset.browser_download_url.clone(),
+                size: asset.size,
+                notes: release.body.clone().unwrap_or_default(),
+                checksum: release.asset_url(&format!("{}{}", asset.name, CHECKSUM_EXTENSION)),
+                signature: release.asset_url(&format!(
+                    "{}{}",
+                    asset.name,
+                    plugin_signature::SIGNATURE_EXTENSION
+                )),
+            })),
+            None => Err(NoReleaseAssetError { version }),
+        },
+        None => Ok(UpdateCheckResult::NoUpdate),
+    }
 }
 
-pub fn start_check_self_update() -> UpdateCheckReceiver {
+fn check_self_update(settings: &UpdateSettings) -> Result<UpdateCheckResult, Error> {
+    let current_version = Version::parse(VERSION)?;
+    let releases = get_releases(&settings.feed_url)?;
+    Ok(find_update(&releases, &current_version, settings)?)
+}
+
+pub fn start_check_self_update(settings: UpdateSettings) -> UpdateCheckReceiver {
     let (tx, rx) = mpsc::channel();
 
     thread::spawn(move || {
-        let message: Result<UpdateCheckResult, Error> = match get_latest_release() {
-            Ok(res) => {
-                let update_available = true; // TODO: restore !!!!!!!!!!!!!!!!!!             res.tag_name > Version::parse(VERSION).unwrap();
-                if update_available {
-                    println!("{:?}", res.assets);
-                    let launcher_asset = res.assets.iter().find(|item| {
-                        item.name.starts_with("libhyperbeam_launcher")
-                            && item.name.ends_with(".nro")
-                    });
-                    match launcher_asset {
-                        Some(asset) => Ok(UpdateCheckResult::UpdateAvailable(Update {
-                            version: res.tag_name,
-                            download: asset.browser_download_url.clone(),
-                        })),
-                        None => Err(Into::<Error>::into(NoReleaseAssetError)),
-                    }
-                } else {
-                    Ok(UpdateCheckResult::NoUpdate)
-                }
-            }
-            Err(err) => Err(Into::<Error>::into(err)),
-        };
-        if let Err(error) = tx.send(message) {
+        if let Err(error) = tx.send(check_self_update(&settings)) {
             eprintln!("Update check thread failed to send message: {:?}", error);
         }
     });
@@ -110,68 +422,410 @@ pub fn start_check_self_update() -> UpdateCheckReceiver {
     rx
 }
 
+fn download_bytes(url: &str) -> Result<Vec<u8>, Error> {
+    Ok(minreq::get(url)
+        .with_header("User-Agent", "hyperbeam-launcher")
+        .with_timeout(10)
+        .send()?
+        .as_bytes()
+        .to_vec())
+}
+
+/// Removes downloads of other versions and of earlier attempts, which can't be resumed anymore.
+/// Downloads that are still being written to are kept.
+fn remove_other_downloads() -> io::Result<()> {
+    let active_downloads = ACTIVE_DOWNLOADS.lock().unwrap();
+    for entry in fs::read_dir(DOWNLOAD_PATH)? {
+        let path = entry?.path();
+        if !active_downloads.contains(&path) {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// A download file reserved for one update attempt until it's dropped
+struct DownloadFile {
+    path: PathBuf,
+}
+
+impl DownloadFile {
+    /// Reserves the first download file of a version that no other thread is writing to. An
+    /// interrupted download in that file is resumed, a thread that is still running keeps its
+    /// file to itself.
+    fn reserve(version: &Version) -> DownloadFile {
+        let mut active_downloads = ACTIVE_DOWNLOADS.lock().unwrap();
+        let path = (0..)
+            .map(|attempt| {
+                let name = match attempt {
+                    0 => format!("{}.zip.part", version),
+                    attempt => format!("{}-{}.zip.part", version, attempt),
+                };
+                Path::new(DOWNLOAD_PATH).join(name)
+            })
+            .find(|path| !active_downloads.contains(path))
+            .unwrap();
+        active_downloads.push(path.clone());
+        DownloadFile { path }
+    }
+}
+
+impl Drop for DownloadFile {
+    fn drop(&mut self) {
+        ACTIVE_DOWNLOADS
+            .lock()
+            .unwrap()
+            .retain(|path| *path != self.path);
+    }
+}
+
+/// Appends a chunk to the download. The progress is sent first, so that nothing is written once
+/// the launcher stopped waiting for the update, e.g. after a timeout.
+fn write_chunk(
+    file: &mut File,
+    chunk: &mut Vec<u8>,
+    progress: &mut DownloadProgress,
+    sender: &Sender<UpdateProgress>,
+) -> Result<(), Error> {
+    progress.downloaded += chunk.len() as u64;
+    sender.send(UpdateProgress::Downloading(*progress))?;
+    file.write_all(chunk)?;
+    chunk.clear();
+    Ok(())
+}
+
 impl Update {
-    pub fn start_update(self) -> UpdateReceiver {
+    pub fn start_update(self, settings: &UpdateSettings) -> UpdateReceiver {
         let (tx, rx) = mpsc::channel();
+        let public_keys = settings.public_keys.clone();
 
         thread::spawn(move || {
-            match self
-                .download_update(&tx)
-                .and_then(|bytes| self.install_update(bytes, &tx))
-            {
-                Ok(_) => tx.send(UpdateProgress::Finished),
-                Err(error) => tx.send(UpdateProgress::Err(error))
+            let download_file = DownloadFile::reserve(&self.version);
+            let download_path = &download_file.path;
+            let result = self.download_update(download_path, &tx).and_then(|_| {
+                if let Err(error) = self.verify_update(download_path, &public_keys) {
+                    // Don't resume a broken download next time
+                    fs::remove_file(download_path)?;
+                    return Err(error);
+                }
+                tx.send(UpdateProgress::Installing)?;
+                update_installer::install(download_path, self.version.to_string())?;
+                fs::remove_file(download_path)?;
+                Ok(())
+            });
+            // A retry started after this message may resume the download
+            drop(download_file);
+            let message = match result {
+                Ok(_) => UpdateProgress::Finished,
+                Err(error) => UpdateProgress::Err(error),
             };
+            if tx.send(message).is_err() {
+                eprintln!("Update thread failed to send message");
+            }
         });
 
         rx
     }
 
-    fn download_update(&self, sender: &Sender<UpdateProgress>) -> Result<Vec<u8>, Error> {
-        let response = minreq::get(self.download.as_str()).send_lazy()?;
+    /// Checks the signature of the download if update keys are configured, unsigned releases are
+    /// rejected then. Otherwise, the download must match the release's checksum. Releases sign the
+    /// SHA-256 digest of the download, so that it doesn't need to be read into memory.
+    fn verify_update(&self, download_path: &Path, public_keys: &[Vec<u8>]) -> Result<(), Error> {
+        if !public_keys.is_empty() {
+            // The checksum comes from the same feed, so it doesn't replace the signature
+            let signature_url = self
+                .signature
+                .as_ref()
+                .ok_or(UpdateVerificationError::InvalidSignature)?;
+            let signature = download_bytes(signature_url)?;
+            let digest = checksums::digest_reader(File::open(download_path)?)?;
+            return match plugin_signature::check_signature(
+                digest.as_ref(),
+                Some(&signature),
+                public_keys,
+            ) {
+                PluginTrust::Trusted => Ok(()),
+                _ => Err(UpdateVerificationError::InvalidSignature.into()),
+            };
+        }
 
-        let mut bytes = Vec::new();
-        let mut progress_update_counter: u64 = 0;
+        let checksum_url = self
+            .checksum
+            .as_ref()
+            .ok_or(UpdateVerificationError::Unverified)?;
+        let checksum = String::from_utf8(download_bytes(checksum_url)?)?;
+        let expected_hash = checksum
+            .split_whitespace()
+            .next()
+            .ok_or(UpdateVerificationError::InvalidChecksum)?
+            .to_lowercase();
+        if checksums::hash_reader(File::open(download_path)?)? == expected_hash {
+            Ok(())
+        } else {
+            Err(UpdateVerificationError::ChecksumMismatch.into())
+        }
+    }
+
+    /// Downloads the update to a file. If a previous download of the same version was
+    /// interrupted, only the missing part is requested.
+    fn download_update(
+        &self,
+        download_path: &Path,
+        sender: &Sender<UpdateProgress>,
+    ) -> Result<(), Error> {
+        fs::create_dir_all(DOWNLOAD_PATH)?;
+        remove_other_downloads()?;
+
+        let mut downloaded = fs::metadata(download_path)
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+        let mut request =
+            minreq::get(self.download.as_str()).with_header("User-Agent", "hyperbeam-launcher");
+        if downloaded > 0 {
+            println!(
+                "[hyperbeam-launcher] Resuming update download at {} bytes",
+                downloaded
+            );
+            request = request.with_header("Range", format!("bytes={}-", downloaded));
+        }
+        let response = request.send_lazy()?;
+
+        let resumed = match response.status_code {
+            206 => true,
+            // The server doesn't support ranges
+            200 => false,
+            // The partial download is already complete
+            416 if downloaded == self.size => return Ok(()),
+            status_code => {
+                if downloaded > 0 {
+                    // The partial download may be invalid, so it's started over next time
+                    fs::remove_file(download_path)?;
+                }
+                return Err(DownloadError { status_code }.into());
+            }
+        };
+        if !resumed {
+            downloaded = 0;
+        }
+        let total = response
+            .headers
+            .get("content-length")
+            .and_then(|length| length.parse::<u64>().ok())
+            .map(|length| downloaded + length)
+            .or_else(|| Some(self.size).filter(|size| *size > 0));
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(download_path)?;
+        let mut progress = DownloadProgress { downloaded, total };
+        let mut chunk = Vec::with_capacity(DOWNLOAD_CHUNK_SIZE);
         for result in response {
-            let (byte, length) = result?;
-            bytes.reserve(length);
-            bytes.push(byte);
-            progress_update_counter += 1;
-
-            if progress_update_counter == 1024 {
-                sender.send(UpdateProgress::Downloading(ProgressPercentage(
-                    bytes.len() as f32 / bytes.capacity() as f32,
-                )));
-                progress_update_counter = 0;
+            let (byte, _) = result?;
+            chunk.push(byte);
+            if chunk.len() == DOWNLOAD_CHUNK_SIZE {
+                write_chunk(&mut file, &mut chunk, &mut progress, sender)?;
             }
         }
+        if !chunk.is_empty() {
+            write_chunk(&mut file, &mut chunk, &mut progress, sender)?;
+        }
+        match total {
+            // The connection was closed early, the partial download is resumed next time
+            Some(total) if progress.downloaded < total => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Download interrupted after {} of {} bytes",
+                    progress.downloaded, total
+                ),
+            )
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
 
-        Ok(bytes)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ASSET_NAME: &str = "hyperbeam.zip";
+
+    fn release(tag_name: &str, prerelease: bool, draft: bool) -> GitHubRelease {
+        let asset = |name: &str| GitHubReleaseAsset {
+            name: name.to_owned(),
+            size: 1234,
+            browser_download_url: format!("https://example.com/{}/{}", tag_name, name),
+        };
+        GitHubRelease {
+            name: Some(tag_name.to_owned()),
+            tag_name: tag_name.to_owned(),
+            prerelease,
+            draft,
+            body: Some(format!("Release notes of {}", tag_name)),
+            assets: vec![
+                asset(ASSET_NAME),
+                asset(&format!("{}{}", ASSET_NAME, CHECKSUM_EXTENSION)),
+            ],
+        }
     }
 
-    fn install_update(&self, bytes: Vec<u8>, sender: &Sender<UpdateProgress>) -> Result<(), Error> {
-        let mut cursor = Cursor::new(bytes);
-        let mut archive = ZipArchive::new(cursor)?;
-        for i in 0..archive.len() {
-            let mut file = archive.by_index(i)?;
-            let path = match file.enclosed_name() {
-                Some(path) => path.to_owned(),
-                None => continue,
-            };
+    fn settings(channel: UpdateChannel, skipped_versions: &[&str]) -> UpdateSettings {
+        UpdateSettings {
+            feed_url: DEFAULT_UPDATE_FEED.to_owned(),
+            asset_pattern: "hyperbeam*.zip".to_owned(),
+            channel,
+            skipped_versions: skipped_versions
+                .iter()
+                .map(|version| Version::parse(version).unwrap())
+                .collect(),
+            public_keys: Vec::new(),
+        }
+    }
 
-            let out_path = PathBuf::from(UPDATE_BASE_PATH).join(path);
-            if (&*file.name()).ends_with('/') {
-                fs::create_dir_all(&out_path)?;
-            } else {
-                if let Some(p) = out_path.parent() {
-                    if !p.exists() {
-                        fs::create_dir_all(&p)?;
-                    }
-                }
-                let mut outfile = File::create(&out_path)?;
-                io::copy(&mut file, &mut outfile)?;
-            }
+    fn check(releases: &[GitHubRelease], settings: &UpdateSettings) -> Option<String> {
+        match find_update(releases, &Version::new(1, 0, 0), settings).unwrap() {
+            UpdateCheckResult::UpdateAvailable(update) => Some(update.version.to_string()),
+            UpdateCheckResult::NoUpdate => None,
         }
-        Ok(())
+    }
+
+    #[test]
+    fn newest_release() {
+        let releases = [
+            release("1.1.0", false, false),
+            release("1.3.0", false, false),
+            release("1.2.0", false, false),
+        ];
+        let result = find_update(
+            &releases,
+            &Version::new(1, 0, 0),
+            &settings(UpdateChannel::Stable, &[]),
+        );
+        assert_eq!(
+            result,
+            Ok(UpdateCheckResult::UpdateAvailable(Update {
+                version: Version::new(1, 3, 0),
+                download: "https://example.com/1.3.0/hyperbeam.zip".to_owned(),
+                size: 1234,
+                notes: "Release notes of 1.3.0".to_owned(),
+                checksum: Some("https://example.com/1.3.0/hyperbeam.zip.sha256".to_owned()),
+                signature: None,
+            }))
+        );
+    }
+
+    #[test]
+    fn older_or_equal_version() {
+        let settings = settings(UpdateChannel::Stable, &[]);
+        assert_eq!(check(&[release("1.0.0", false, false)], &settings), None);
+        assert_eq!(check(&[release("0.9.0", false, false)], &settings), None);
+        assert_eq!(check(&[], &settings), None);
+    }
+
+    #[test]
+    fn prerelease_channel() {
+        let releases = [
+            release("1.1.0", false, false),
+            release("1.2.0-beta.1", true, false),
+        ];
+        assert_eq!(
+            check(&releases, &settings(UpdateChannel::Stable, &[])).as_deref(),
+            Some("1.1.0")
+        );
+        assert_eq!(
+            check(&releases, &settings(UpdateChannel::Prerelease, &[])).as_deref(),
+            Some("1.2.0-beta.1")
+        );
+    }
+
+    #[test]
+    fn ignore_drafts() {
+        let releases = [
+            release("1.1.0", false, false),
+            release("1.2.0", false, true),
+        ];
+        assert_eq!(
+            check(&releases, &settings(UpdateChannel::Prerelease, &[])).as_deref(),
+            Some("1.1.0")
+        );
+    }
+
+    #[test]
+    fn tag_names() {
+        let settings = settings(UpdateChannel::Stable, &[]);
+        assert_eq!(
+            check(&[release("v1.1.0", false, false)], &settings).as_deref(),
+            Some("1.1.0")
+        );
+        assert_eq!(check(&[release("nightly", false, false)], &settings), None);
+        assert_eq!(
+            check(
+                &[
+                    release("nightly", false, false),
+                    release("v1.1.0", false, false)
+                ],
+                &settings
+            )
+            .as_deref(),
+            Some("1.1.0")
+        );
+    }
+
+    #[test]
+    fn skipped_version() {
+        let releases = [
+            release("1.1.0", false, false),
+            release("1.2.0", false, false),
+        ];
+        assert_eq!(
+            check(&releases, &settings(UpdateChannel::Stable, &["1.2.0"])).as_deref(),
+            Some("1.1.0")
+        );
+        assert_eq!(
+            check(
+                &releases,
+                &settings(UpdateChannel::Stable, &["1.1.0", "1.2.0"])
+            ),
+            None
+        );
+        // A newer release is offered even if an older one was skipped
+        assert_eq!(
+            check(&releases, &settings(UpdateChannel::Stable, &["1.1.0"])).as_deref(),
+            Some("1.2.0")
+        );
+    }
+
+    #[test]
+    fn default_asset_pattern() {
+        for name in &["hyperbeam.zip", "hyperbeam-1.2.0.zip"] {
+            assert!(matches_pattern(name, DEFAULT_ASSET_PATTERN), "{}", name);
+        }
+        for name in &[
+            "hyperbeam.zip.sha256",
+            "hyperbeam.zip.sig",
+            "libhyperbeam_launcher.nro",
+        ] {
+            assert!(!matches_pattern(name, DEFAULT_ASSET_PATTERN), "{}", name);
+        }
+    }
+
+    #[test]
+    fn missing_asset() {
+        let mut newest = release("1.2.0", false, false);
+        newest.assets.retain(|asset| asset.name != ASSET_NAME);
+        let releases = [release("1.1.0", false, false), newest];
+        assert_eq!(
+            find_update(
+                &releases,
+                &Version::new(1, 0, 0),
+                &settings(UpdateChannel::Stable, &[])
+            ),
+            Err(NoReleaseAssetError {
+                version: Version::new(1, 2, 0)
+            })
+        );
     }
 }
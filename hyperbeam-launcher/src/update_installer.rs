@@ -0,0 +1,219 @@
+//! Installs launcher updates without leaving a half-updated launcher behind.
+//!
+//! The update is extracted into a staging folder first. Its files are then moved into place one by
+//! one, and the files they replace are moved to a folder with the previous version. A journal
+//! records the progress, so that an interrupted installation or an update that fails to reach the
+//! modpack selection can be rolled back on the next start.
+
+use crate::fs_helpers::remove_dir_if_exists;
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+/// Files in the update archive are relative to this folder
+pub const UPDATE_BASE_PATH: &str = "sd:/atmosphere/contents/01003D200BAA2000/romfs";
+/// The update is extracted here before its files are moved into place
+const STAGING_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/staging";
+/// Files replaced by the update, kept until the new version started successfully
+const PREVIOUS_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/previous";
+const JOURNAL_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/journal.yaml";
+
+type Error = Box<dyn error::Error + Send + Sync>;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum UpdateState {
+    /// Files are being moved into place
+    Installing,
+    /// All files were installed, the new version didn't start yet
+    Installed,
+    /// The new version started, but didn't reach the modpack selection yet
+    Starting,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UpdateJournal {
+    version: String,
+    state: UpdateState,
+    /// Files of the update, relative to [`UPDATE_BASE_PATH`]
+    files: Vec<PathBuf>,
+}
+
+impl UpdateJournal {
+    fn load() -> Option<UpdateJournal> {
+        let journal_string = fs::read_to_string(JOURNAL_PATH).ok()?;
+        match serde_yaml::from_str(&journal_string) {
+            Ok(journal) => Some(journal),
+            Err(error) => {
+                eprintln!("[hyperbeam-launcher] Invalid update journal: {}", error);
+                None
+            }
+        }
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        fs::write(JOURNAL_PATH, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn set_state(&mut self, state: UpdateState) -> Result<(), Error> {
+        self.state = state;
+        self.save()
+    }
+}
+
+fn remove_journal() {
+    if let Err(error) = fs::remove_file(JOURNAL_PATH) {
+        if error.kind() != io::ErrorKind::NotFound {
+            eprintln!(
+                "[hyperbeam-launcher] Failed to remove the update journal: {}",
+                error
+            );
+        }
+    }
+}
+
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)
+}
+
+/// Extracts the update into the staging folder and returns the paths of its files.
+fn extract(archive_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut archive = ZipArchive::new(File::open(archive_path)?)?;
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let path = match file.enclosed_name() {
+            Some(path) => path.to_owned(),
+            None => continue,
+        };
+
+        let out_path = Path::new(STAGING_PATH).join(&path);
+        if file.is_dir() {
+            fs::create_dir_all(&out_path)?;
+        } else {
+            if let Some(parent) = out_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&out_path)?)?;
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Moves the staged files into place, keeping the files they replace.
+fn swap_in(journal: &UpdateJournal) -> io::Result<()> {
+    for path in &journal.files {
+        let installed_path = Path::new(UPDATE_BASE_PATH).join(path);
+        if installed_path.exists() {
+            move_file(&installed_path, &Path::new(PREVIOUS_PATH).join(path))?;
+        }
+        move_file(&Path::new(STAGING_PATH).join(path), &installed_path)?;
+    }
+    Ok(())
+}
+
+/// Restores the files of the previous version. Works for interrupted installations too: files
+/// that are still staged weren't replaced yet, and files without a previous version that are
+/// neither staged are new and get removed.
+fn roll_back(journal: &UpdateJournal) -> io::Result<()> {
+    for path in &journal.files {
+        let installed_path = Path::new(UPDATE_BASE_PATH).join(path);
+        let previous_path = Path::new(PREVIOUS_PATH).join(path);
+        if previous_path.exists() {
+            if installed_path.exists() {
+                fs::remove_file(&installed_path)?;
+            }
+            fs::rename(&previous_path, &installed_path)?;
+        } else if !Path::new(STAGING_PATH).join(path).exists() && installed_path.exists() {
+            fs::remove_file(&installed_path)?;
+        }
+    }
+    remove_dir_if_exists(Path::new(STAGING_PATH))?;
+    remove_dir_if_exists(Path::new(PREVIOUS_PATH))?;
+    remove_journal();
+    Ok(())
+}
+
+/// Installs an update archive. If any file can't be installed, the previous version is restored.
+pub fn install(archive_path: &Path, version: String) -> Result<(), Error> {
+    remove_dir_if_exists(Path::new(STAGING_PATH))?;
+    remove_dir_if_exists(Path::new(PREVIOUS_PATH))?;
+    fs::create_dir_all(STAGING_PATH)?;
+    let files = match extract(archive_path) {
+        Ok(files) => files,
+        Err(error) => {
+            remove_dir_if_exists(Path::new(STAGING_PATH))?;
+            return Err(error);
+        }
+    };
+
+    let mut journal = UpdateJournal {
+        version,
+        state: UpdateState::Installing,
+        files,
+    };
+    journal.save()?;
+    if let Err(error) = swap_in(&journal) {
+        roll_back(&journal)?;
+        return Err(error.into());
+    }
+    journal.set_state(UpdateState::Installed)?;
+    remove_dir_if_exists(Path::new(STAGING_PATH))?;
+    Ok(())
+}
+
+/// Called when the launcher starts. Rolls back interrupted installations and updates that were
+/// started before without reaching the modpack selection. The rollback takes effect on the next
+/// start.
+pub fn check_installed_update() {
+    let mut journal = match UpdateJournal::load() {
+        Some(journal) => journal,
+        None => return,
+    };
+    let result = match journal.state {
+        UpdateState::Installed => journal.set_state(UpdateState::Starting),
+        UpdateState::Installing | UpdateState::Starting => {
+            eprintln!(
+                "[hyperbeam-launcher] Update to version {} failed, restoring the previous version...",
+                journal.version
+            );
+            roll_back(&journal).map_err(Error::from)
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("[hyperbeam-launcher] Failed to check the update: {}", error);
+    }
+}
+
+/// Called when the modpack selection is reached. Removes the previous version once an update
+/// started successfully.
+pub fn confirm_update() {
+    match UpdateJournal::load() {
+        Some(journal) if journal.state == UpdateState::Starting => {
+            println!(
+                "[hyperbeam-launcher] Updated to version {}.",
+                journal.version
+            );
+            if let Err(error) = remove_dir_if_exists(Path::new(PREVIOUS_PATH)) {
+                eprintln!(
+                    "[hyperbeam-launcher] Failed to remove the previous version: {}",
+                    error
+                );
+            }
+            remove_journal();
+        }
+        _ => {}
+    }
+}
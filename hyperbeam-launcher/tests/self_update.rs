@@ -0,0 +1,619 @@
+//! Runs the update check and the update installation against a local HTTP server.
+//!
+//! The launcher itself only builds as a Switch plugin, so the modules used by the updater are
+//! compiled into this test directly. Their `sd:/` paths are relative on a PC, so the tests run
+//! in a temporary folder.
+
+#[allow(dead_code)]
+#[path = "../src/checksums.rs"]
+mod checksums;
+#[allow(dead_code)]
+#[path = "../src/config.rs"]
+mod config;
+#[allow(dead_code)]
+#[path = "../src/fs_helpers.rs"]
+mod fs_helpers;
+#[allow(dead_code)]
+#[path = "../src/modpack_files.rs"]
+mod modpack_files;
+#[allow(dead_code)]
+#[path = "../src/plugin_signature.rs"]
+mod plugin_signature;
+#[allow(dead_code)]
+#[path = "../src/save_backup.rs"]
+mod save_backup;
+#[allow(dead_code)]
+#[path = "../src/self_update.rs"]
+mod self_update;
+#[allow(dead_code)]
+#[path = "../src/update_installer.rs"]
+mod update_installer;
+
+use config::UpdateChannel;
+use ring::rand::SystemRandom;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use self_update::{
+    matches_pattern, start_check_self_update, DownloadProgress, Update, UpdateCheckResult,
+    UpdateProgress, UpdateSettings,
+};
+use semver::Version;
+use serde_json::json;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const DOWNLOAD_PATH: &str =
+    "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/update/download";
+const LAUNCHER_PATH: &str = "skyline/plugins/libhyperbeam_launcher.nro";
+const ASSET_NAME: &str = "hyperbeam.zip";
+
+lazy_static::lazy_static! {
+    /// The tests that install updates share the launcher's folders
+    static ref FILE_SYSTEM: Mutex<TempDir> = {
+        let dir = TempDir::new().unwrap();
+        env::set_current_dir(dir.path()).unwrap();
+        Mutex::new(dir)
+    };
+}
+
+/// Locks the temporary folder the tests run in and removes the files of previous tests
+fn lock_file_system() -> MutexGuard<'static, TempDir> {
+    let guard = FILE_SYSTEM
+        .lock()
+        .unwrap_or_else(|error| error.into_inner());
+    if let Err(error) = fs::remove_dir_all("sd:") {
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+    guard
+}
+
+/// Stops the next response of a route after part of the body
+struct Interruption {
+    length: usize,
+    /// The rest of the body is sent once this receives a message. Without it, the connection is
+    /// closed.
+    resume: Option<Receiver<()>>,
+}
+
+struct Route {
+    body: Vec<u8>,
+    interruption: Option<Interruption>,
+}
+
+/// HTTP server that serves fixed responses and supports `Range` requests
+struct TestServer {
+    address: SocketAddr,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    /// Requested paths, followed by the requested range if there is one
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    fn start() -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let server = TestServer {
+            address: listener.local_addr().unwrap(),
+            routes: Arc::default(),
+            requests: Arc::default(),
+        };
+        let routes = server.routes.clone();
+        let requests = server.requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let routes = routes.clone();
+                let requests = requests.clone();
+                thread::spawn(move || respond(stream, &routes, &requests));
+            }
+        });
+        server
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    fn serve(&self, path: &str, body: Vec<u8>) {
+        self.routes.lock().unwrap().insert(
+            path.to_owned(),
+            Route {
+                body,
+                interruption: None,
+            },
+        );
+    }
+
+    fn interrupt_next(&self, path: &str, interruption: Interruption) {
+        self.routes
+            .lock()
+            .unwrap()
+            .get_mut(path)
+            .unwrap()
+            .interruption = Some(interruption);
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn respond(
+    mut stream: TcpStream,
+    routes: &Mutex<HashMap<String, Route>>,
+    requests: &Mutex<Vec<String>>,
+) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let path = request_line
+        .split(' ')
+        .nth(1)
+        .unwrap_or_default()
+        .to_owned();
+    let mut range_start = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap() == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        let line = line.trim_end().to_ascii_lowercase();
+        if let Some(range) = line.strip_prefix("range: bytes=") {
+            range_start = range.trim_end_matches('-').parse::<usize>().ok();
+        }
+    }
+    requests.lock().unwrap().push(match range_start {
+        Some(start) => format!("{} bytes={}-", path, start),
+        None => path.clone(),
+    });
+
+    let (status, body, interruption) = match routes.lock().unwrap().get_mut(&path) {
+        None => ("404 Not Found", Vec::new(), None),
+        Some(route) => match range_start {
+            Some(start) if start >= route.body.len() => {
+                ("416 Range Not Satisfiable", Vec::new(), None)
+            }
+            Some(start) => (
+                "206 Partial Content",
+                route.body[start..].to_vec(),
+                route.interruption.take(),
+            ),
+            None => ("200 OK", route.body.clone(), route.interruption.take()),
+        },
+    };
+    // The client may have given up already
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )
+    .and_then(|_| match interruption {
+        None => stream.write_all(&body),
+        Some(Interruption { length, resume }) => {
+            stream.write_all(&body[..length])?;
+            match resume {
+                Some(resume) if resume.recv().is_ok() => stream.write_all(&body[length..]),
+                _ => Ok(()),
+            }
+        }
+    });
+}
+
+fn settings(feed_url: String) -> UpdateSettings {
+    UpdateSettings {
+        feed_url,
+        asset_pattern: "hyperbeam*.zip".to_owned(),
+        channel: UpdateChannel::Stable,
+        skipped_versions: Vec::new(),
+        public_keys: Vec::new(),
+    }
+}
+
+fn check_update(settings: UpdateSettings) -> UpdateCheckResult {
+    start_check_self_update(settings)
+        .recv()
+        .unwrap()
+        .unwrap_or_else(|error| panic!("Update check failed: {}", error))
+}
+
+fn expected_update(server: &TestServer) -> UpdateCheckResult {
+    UpdateCheckResult::UpdateAvailable(Update {
+        version: Version::new(9, 0, 0),
+        download: server.url("/download/hyperbeam.zip"),
+        size: 1234,
+        notes: "Release notes".to_owned(),
+        checksum: Some(server.url("/download/hyperbeam.zip.sha256")),
+        signature: None,
+    })
+}
+
+fn github_release(server: &TestServer, tag_name: &str, prerelease: bool) -> serde_json::Value {
+    json!({
+        "name": tag_name,
+        "tag_name": tag_name,
+        "prerelease": prerelease,
+        "draft": false,
+        "body": "Release notes",
+        "assets": [
+            {
+                "name": ASSET_NAME,
+                "size": 1234,
+                "browser_download_url": server.url("/download/hyperbeam.zip"),
+            },
+            {
+                "name": "hyperbeam.zip.sha256",
+                "size": 80,
+                "browser_download_url": server.url("/download/hyperbeam.zip.sha256"),
+            },
+        ],
+    })
+}
+
+#[test]
+fn check_github_releases() {
+    let server = TestServer::start();
+    let mut releases = json!([
+        github_release(&server, "v0.0.1", false),
+        github_release(&server, "v10.0.0-beta.1", true),
+        github_release(&server, "v9.0.0", false),
+    ]);
+    // Untitled releases don't have a name
+    let mut untitled_release = github_release(&server, "v0.0.2", false);
+    untitled_release["name"] = serde_json::Value::Null;
+    releases.as_array_mut().unwrap().push(untitled_release);
+    server.serve("/releases", releases.to_string().into_bytes());
+    assert_eq!(
+        check_update(settings(server.url("/releases"))),
+        expected_update(&server)
+    );
+}
+
+#[test]
+fn check_single_github_release() {
+    let server = TestServer::start();
+    let release = github_release(&server, "v9.0.0", false);
+    server.serve("/releases/latest", release.to_string().into_bytes());
+    assert_eq!(
+        check_update(settings(server.url("/releases/latest"))),
+        expected_update(&server)
+    );
+}
+
+#[test]
+fn check_update_manifest() {
+    let server = TestServer::start();
+    let manifest = json!({
+        "releases": [
+            {
+                "version": "9.0.0",
+                "notes": "Release notes",
+                "assets": [
+                    {
+                        "name": ASSET_NAME,
+                        "size": 1234,
+                        "url": server.url("/download/hyperbeam.zip"),
+                    },
+                    {
+                        "name": "hyperbeam.zip.sha256",
+                        "url": server.url("/download/hyperbeam.zip.sha256"),
+                    },
+                ],
+            },
+            {
+                "version": "10.0.0-beta.1",
+                "prerelease": true,
+                "assets": [],
+            },
+        ],
+    });
+    server.serve("/manifest.json", manifest.to_string().into_bytes());
+    assert_eq!(
+        check_update(settings(server.url("/manifest.json"))),
+        expected_update(&server)
+    );
+}
+
+#[test]
+fn check_unavailable_feed() {
+    let server = TestServer::start();
+    assert!(start_check_self_update(settings(server.url("/releases")))
+        .recv()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn asset_patterns() {
+    assert!(matches_pattern("hyperbeam.zip", "hyperbeam.zip"));
+    assert!(!matches_pattern("hyperbeam.zip.sha256", "hyperbeam.zip"));
+    assert!(matches_pattern("hyperbeam-1.2.0.zip", "hyperbeam*.zip"));
+    assert!(matches_pattern("hyperbeam.zip", "hyperbeam*.zip"));
+    assert!(!matches_pattern("hyperbeam.zip.sha256", "hyperbeam*.zip"));
+    assert!(!matches_pattern("other.zip", "hyperbeam*.zip"));
+    assert!(matches_pattern("anything", "*"));
+    assert!(matches_pattern(
+        "hyperbeam-switch-1.2.0.zip",
+        "hyperbeam*switch*.zip"
+    ));
+    assert!(!matches_pattern(
+        "hyperbeam-1.2.0.zip",
+        "hyperbeam*switch*.zip"
+    ));
+    // The prefix and suffix can't overlap
+    assert!(!matches_pattern("ab", "ab*b"));
+}
+
+/// Creates an update archive that replaces the launcher and contains `padding` bytes of
+/// uncompressed data, so that the download can be interrupted.
+fn update_archive(padding: usize) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    writer.start_file(LAUNCHER_PATH, options).unwrap();
+    writer.write_all(b"new launcher").unwrap();
+    writer.start_file("hyperbeam/padding.bin", options).unwrap();
+    let padding: Vec<u8> = (0..padding).map(|i| (i * 7 % 251) as u8).collect();
+    writer.write_all(&padding).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+/// Serves an update archive with its checksum and returns the update to install
+fn serve_update(server: &TestServer, archive: Vec<u8>) -> Update {
+    let hash = checksums::hash_reader(&archive[..]).unwrap();
+    server.serve(
+        "/download/hyperbeam.zip.sha256",
+        format!("{}  {}\n", hash, ASSET_NAME).into_bytes(),
+    );
+    let size = archive.len() as u64;
+    server.serve("/download/hyperbeam.zip", archive);
+    Update {
+        version: Version::new(9, 0, 0),
+        download: server.url("/download/hyperbeam.zip"),
+        size,
+        notes: String::new(),
+        checksum: Some(server.url("/download/hyperbeam.zip.sha256")),
+        signature: None,
+    }
+}
+
+/// Runs an update and returns the reported download progress
+fn run_update(update: Update, settings: &UpdateSettings) -> Result<Vec<DownloadProgress>, String> {
+    let receiver = update.start_update(settings);
+    let mut progress = Vec::new();
+    loop {
+        match receiver.recv().unwrap() {
+            UpdateProgress::Downloading(download_progress) => progress.push(download_progress),
+            UpdateProgress::Installing => {}
+            UpdateProgress::Finished => return Ok(progress),
+            UpdateProgress::Err(error) => return Err(error.to_string()),
+        }
+    }
+}
+
+fn installed_path(path: &str) -> PathBuf {
+    Path::new(update_installer::UPDATE_BASE_PATH).join(path)
+}
+
+fn install_old_launcher() {
+    let launcher_path = installed_path(LAUNCHER_PATH);
+    fs::create_dir_all(launcher_path.parent().unwrap()).unwrap();
+    fs::write(launcher_path, "old launcher").unwrap();
+}
+
+fn downloads() -> Vec<PathBuf> {
+    match fs::read_dir(DOWNLOAD_PATH) {
+        Ok(dir) => dir.map(|entry| entry.unwrap().path()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+#[test]
+fn install_update() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let update = serve_update(&server, update_archive(0));
+
+    run_update(update, &settings(String::new())).unwrap();
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "new launcher"
+    );
+    assert!(downloads().is_empty());
+}
+
+#[test]
+fn reject_update_with_wrong_checksum() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let update = serve_update(&server, update_archive(0));
+    server.serve(
+        "/download/hyperbeam.zip.sha256",
+        format!("{}  {}\n", "0".repeat(64), ASSET_NAME).into_bytes(),
+    );
+
+    assert_eq!(
+        run_update(update, &settings(String::new())).unwrap_err(),
+        "The download doesn't match the update checksum"
+    );
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "old launcher"
+    );
+    // The broken download isn't resumed
+    assert!(downloads().is_empty());
+}
+
+#[test]
+fn resume_interrupted_download() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let update = serve_update(&server, update_archive(300_000));
+    let total = update.size;
+    server.interrupt_next(
+        "/download/hyperbeam.zip",
+        Interruption {
+            length: 100_000,
+            resume: None,
+        },
+    );
+
+    let error = run_update(update.clone(), &settings(String::new())).unwrap_err();
+    assert!(error.contains("interrupted"), "{}", error);
+    let partial_downloads = downloads();
+    assert_eq!(partial_downloads.len(), 1);
+    assert_eq!(fs::metadata(&partial_downloads[0]).unwrap().len(), 100_000);
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "old launcher"
+    );
+
+    let progress = run_update(update, &settings(String::new())).unwrap();
+    assert!(server
+        .requests()
+        .contains(&"/download/hyperbeam.zip bytes=100000-".to_owned()));
+    assert!(!progress.is_empty());
+    assert!(progress
+        .iter()
+        .all(|progress| progress.downloaded > 100_000 && progress.total == Some(total)));
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "new launcher"
+    );
+    assert!(downloads().is_empty());
+}
+
+/// Serves an update archive with a signature of the given data and returns the update and the
+/// settings with the signing key
+fn serve_signed_update(
+    server: &TestServer,
+    archive: Vec<u8>,
+    signed_data: impl FnOnce(&[u8]) -> Vec<u8>,
+) -> (Update, UpdateSettings) {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+    let signature = key_pair.sign(&signed_data(&archive));
+    server.serve("/download/hyperbeam.zip.sig", signature.as_ref().to_vec());
+    let mut update = serve_update(server, archive);
+    update.checksum = None;
+    update.signature = Some(server.url("/download/hyperbeam.zip.sig"));
+    let mut settings = settings(String::new());
+    settings.public_keys = vec![key_pair.public_key().as_ref().to_vec()];
+    (update, settings)
+}
+
+#[test]
+fn install_signed_update() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let (update, settings) = serve_signed_update(&server, update_archive(0), |archive| {
+        checksums::digest_reader(archive).unwrap().as_ref().to_vec()
+    });
+
+    run_update(update, &settings).unwrap();
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "new launcher"
+    );
+}
+
+#[test]
+fn reject_signature_of_archive_contents() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    // Updates are signed by their digest, not by the archive itself
+    let (update, settings) =
+        serve_signed_update(&server, update_archive(0), |archive| archive.to_vec());
+
+    assert_eq!(
+        run_update(update, &settings).unwrap_err(),
+        "The update isn't signed by a trusted key"
+    );
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "old launcher"
+    );
+}
+
+#[test]
+fn reject_unsigned_update_with_keys() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let (mut update, settings) = serve_signed_update(&server, update_archive(0), |archive| {
+        checksums::digest_reader(archive).unwrap().as_ref().to_vec()
+    });
+    // The release still has a matching checksum, which isn't enough once keys are configured
+    update.signature = None;
+    update.checksum = Some(server.url("/download/hyperbeam.zip.sha256"));
+
+    assert_eq!(
+        run_update(update, &settings).unwrap_err(),
+        "The update isn't signed by a trusted key"
+    );
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "old launcher"
+    );
+}
+
+#[test]
+fn retry_while_previous_attempt_is_running() {
+    let _file_system = lock_file_system();
+    install_old_launcher();
+    let server = TestServer::start();
+    let update = serve_update(&server, update_archive(300_000));
+    let (resume, resume_receiver) = mpsc::channel();
+    server.interrupt_next(
+        "/download/hyperbeam.zip",
+        Interruption {
+            length: 100_000,
+            resume: Some(resume_receiver),
+        },
+    );
+
+    // The first attempt stalls after writing a chunk, and the launcher stops waiting for it
+    let first_attempt = update.clone().start_update(&settings(String::new()));
+    match first_attempt.recv().unwrap() {
+        UpdateProgress::Downloading(progress) => assert_eq!(progress.downloaded, 0x10000),
+        _ => panic!("Expected download progress"),
+    }
+    drop(first_attempt);
+    let stalled_download = downloads();
+    assert_eq!(stalled_download.len(), 1);
+
+    // The retry doesn't resume or remove the file the first attempt is still writing to
+    run_update(update, &settings(String::new())).unwrap();
+    assert_eq!(
+        server.requests(),
+        [
+            "/download/hyperbeam.zip",
+            "/download/hyperbeam.zip",
+            "/download/hyperbeam.zip.sha256"
+        ]
+    );
+    assert_eq!(
+        fs::read_to_string(installed_path(LAUNCHER_PATH)).unwrap(),
+        "new launcher"
+    );
+    assert_eq!(downloads(), stalled_download);
+
+    // Once it continues, the first attempt stops without writing anything else
+    resume.send(()).unwrap();
+    for _ in 0..50 {
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(fs::metadata(&stalled_download[0]).unwrap().len(), 0x10000);
+    }
+}
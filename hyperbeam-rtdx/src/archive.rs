@@ -0,0 +1,12 @@
+use std::io;
+use zip::result::ZipError;
+
+/// Converts errors of the zip crate, so that missing entries can be told apart from I/O errors
+/// and broken archives.
+pub fn to_io_error(error: ZipError) -> io::Error {
+    match error {
+        ZipError::Io(error) => error,
+        ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, error),
+        error => io::Error::new(io::ErrorKind::InvalidData, error),
+    }
+}
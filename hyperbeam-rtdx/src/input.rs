@@ -9,7 +9,7 @@ pub enum Button {
     A = 1,
     B = 2,
     X = 1024,
-    Y = 2028,
+    Y = 2048,
     R = 256,
     L = 512,
     ZR = 4096,
@@ -73,3 +73,38 @@ fn get_input_system_instance() -> &'static mut pmd::InputSystem {
         INPUT_SYSTEM.as_mut().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_buttons_are_distinct_bits() {
+        let buttons = [
+            Button::A,
+            Button::B,
+            Button::X,
+            Button::Y,
+            Button::R,
+            Button::L,
+            Button::ZR,
+            Button::ZL,
+            Button::SR,
+            Button::SL,
+            Button::Select,
+            Button::Start,
+            Button::Right,
+            Button::Left,
+            Button::Up,
+            Button::Down,
+        ];
+        let mut mask = 0;
+        for button in buttons.iter() {
+            let bits = *button as u32;
+            assert_eq!(bits.count_ones(), 1, "{:#x}", bits);
+            assert_eq!(mask & bits, 0, "{:#x}", bits);
+            mask |= bits;
+        }
+        assert_eq!(mask, Button::All as u32);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod archive;
 pub mod input;
 pub mod modpack;
 pub mod serialization;
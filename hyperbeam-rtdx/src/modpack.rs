@@ -1,9 +1,19 @@
 use crate::serialization;
-use semver::Version;
+use semver::{Version, VersionReq};
+use serde::de::IgnoredAny;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
 
 pub static MODPACK_BASE_PATH: &str =
     "sd:/atmosphere/contents/01003D200BAA2000/romfs/hyperbeam/modpacks";
+/// Extension of single-file modpacks, which are zip archives of a modpack folder
+pub static MODPACK_ARCHIVE_EXTENSION: &str = "hbpack";
+
+pub fn is_modpack_archive(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new(MODPACK_ARCHIVE_EXTENSION)) && path.is_file()
+}
 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -14,4 +24,132 @@ pub struct ModpackMetadata {
     #[serde(deserialize_with = "serialization::from_semver")]
     pub version: Version,
     pub target: String,
+    /// Longer description shown on the modpack's detail page in the launcher
+    pub description: Option<String>,
+    pub website: Option<String>,
+    /// Names of everyone who contributed to the modpack
+    #[serde(default)]
+    pub credits: Vec<String>,
+    pub license: Option<String>,
+    /// Changes in this version of the modpack
+    pub changelog: Option<String>,
+    /// Versions of hyperbeam the modpack works with, e.g. `">=0.2"`
+    #[serde(
+        default = "serialization::any_version",
+        deserialize_with = "serialization::from_semver_req"
+    )]
+    pub hyperbeam_version: VersionReq,
+    /// IDs of modpacks this modpack is layered on top of, from lowest to highest priority
+    #[serde(default)]
+    pub layers: Vec<String>,
+    /// romfs paths that should be hidden from the game as if they didn't exist.
+    /// Files can also be hidden by placing a `<file name>.hbdelete` marker in the romfs folder.
+    #[serde(default)]
+    pub deleted_files: Vec<String>,
+    /// Use the base game's save data instead of a separate save for this modpack.
+    /// Only intended for modpacks that don't change any data stored in the save.
+    #[serde(default)]
+    pub shared_save: bool,
+    /// Modpacks that are loaded below this modpack and must be installed
+    #[serde(default)]
+    pub dependencies: Vec<ModpackReference>,
+    /// Modpacks that can't be loaded together with this modpack
+    #[serde(default)]
+    pub conflicts: Vec<ModpackReference>,
+    /// IDs of modpacks that should be loaded before (below) this modpack if they're loaded at all
+    #[serde(default)]
+    pub load_after: Vec<String>,
+    /// IDs of modpacks that should be loaded after (above) this modpack if they're loaded at all
+    #[serde(default)]
+    pub load_before: Vec<String>,
+    /// Plugins to load, in this order. If missing, all NROs in the `plugins` folder are loaded
+    /// in alphabetical order and are required.
+    pub plugins: Option<Vec<PluginEntry>>,
+    /// Keys that this version of hyperbeam doesn't know about
+    #[serde(flatten)]
+    pub unknown_keys: BTreeMap<String, IgnoredAny>,
+}
+
+/// A plugin in the `plugins` section of `modpack.yaml`, e.g. `{ name: extra_music.nro, optional: true }`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginEntry {
+    /// File name of the NRO in the `plugins` folder
+    pub name: String,
+    /// Optional plugins can be disabled by the user
+    #[serde(default)]
+    pub optional: bool,
+    /// Whether the game can't be started if the plugin fails to load.
+    /// Defaults to `true`, unless the plugin is optional.
+    pub required: Option<bool>,
+}
+
+impl PluginEntry {
+    pub fn is_required(&self) -> bool {
+        self.required.unwrap_or(!self.optional)
+    }
+}
+
+/// A modpack ID with a version requirement, e.g. `{ id: base.overhaul, version: ">=1.2, <2" }`
+#[derive(Debug, Deserialize, Clone)]
+pub struct ModpackReference {
+    pub id: String,
+    #[serde(
+        default = "serialization::any_version",
+        deserialize_with = "serialization::from_semver_req"
+    )]
+    pub version: VersionReq,
+}
+
+#[derive(Debug, Clone)]
+pub struct ModpackLayer {
+    pub metadata: ModpackMetadata,
+    pub path: PathBuf,
+}
+
+impl ModpackLayer {
+    /// Whether the layer is a modpack archive instead of a folder
+    pub fn is_archive(&self) -> bool {
+        is_modpack_archive(&self.path)
+    }
+
+    /// The layer's romfs folder, or `None` for archives, whose romfs can only be read through
+    /// the archive
+    pub fn romfs_path(&self) -> Option<PathBuf> {
+        if self.is_archive() {
+            None
+        } else {
+            Some(self.path.join("romfs"))
+        }
+    }
+}
+
+/// The modpack selected in the launcher, as passed to modpack plugins.
+#[derive(Debug, Clone)]
+pub struct CurrentModpack {
+    pub metadata: ModpackMetadata,
+    pub path: PathBuf,
+    /// All modpacks that make up the overlay filesystem, highest priority first.
+    /// The first layer is always the selected modpack itself.
+    pub layers: Vec<ModpackLayer>,
+}
+
+impl CurrentModpack {
+    /// The modpack's romfs folder, or `None` if the modpack is an archive, whose romfs can only be
+    /// read through the archive
+    pub fn romfs_path(&self) -> Option<PathBuf> {
+        if is_modpack_archive(&self.path) {
+            None
+        } else {
+            Some(self.path.join("romfs"))
+        }
+    }
+
+    /// The romfs folders of all layers that aren't archives, highest priority first
+    pub fn romfs_layers(&self) -> Vec<PathBuf> {
+        self.layers
+            .iter()
+            .filter_map(ModpackLayer::romfs_path)
+            .collect()
+    }
 }
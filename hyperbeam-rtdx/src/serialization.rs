@@ -1,4 +1,4 @@
-use semver::Version;
+use semver::{Version, VersionReq};
 use serde::{Deserialize, Deserializer};
 
 pub fn from_semver<'de, D>(deserializer: D) -> Result<Version, D::Error>
@@ -8,3 +8,15 @@ where
     let s: String = Deserialize::deserialize(deserializer)?;
     Version::parse(&s).map_err(serde::de::Error::custom)
 }
+
+pub fn from_semver_req<'de, D>(deserializer: D) -> Result<VersionReq, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: String = Deserialize::deserialize(deserializer)?;
+    VersionReq::parse(&s).map_err(serde::de::Error::custom)
+}
+
+pub fn any_version() -> VersionReq {
+    VersionReq::STAR
+}
@@ -0,0 +1,30 @@
+#!/bin/sh
+# Writes the checksums file of a modpack folder, which lets the launcher detect missing or
+# damaged files. Run it again after changing any file in romfs/ or plugins/.
+#
+# Usage: tools/generate_checksums.sh <modpack folder>
+set -e
+
+if [ $# -ne 1 ] || [ ! -f "$1/modpack.yaml" ]; then
+    echo "Usage: $0 <modpack folder>" >&2
+    exit 1
+fi
+
+if command -v sha256sum > /dev/null; then
+    hash_command="sha256sum"
+else
+    # macOS
+    hash_command="shasum -a 256"
+fi
+
+cd "$1"
+for dir in romfs plugins; do
+    if [ -d "$dir" ]; then
+        find "$dir" -type f
+    fi
+done | LC_ALL=C sort | while IFS= read -r file; do
+    $hash_command "$file"
+done > checksums.tmp
+mv checksums.tmp checksums
+
+echo "Wrote checksums for $(wc -l < checksums | tr -d ' ') files to $1/checksums"
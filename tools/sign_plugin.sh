@@ -0,0 +1,22 @@
+#!/bin/sh
+# Signs a plugin NRO with an ed25519 private key, so that the launcher loads it without
+# confirmation for players who trust the key. Writes the signature next to the plugin
+# (`<plugin>.nro.sig`) and prints the public key to add to `trustedKeys` in config.yaml.
+#
+# Create a private key once with:
+#   openssl genpkey -algorithm ed25519 -out hyperbeam_signing_key.pem
+#
+# Usage: tools/sign_plugin.sh <private key> <plugin.nro>
+set -e
+
+if [ $# -ne 2 ]; then
+    echo "Usage: $0 <private key> <plugin.nro>" >&2
+    exit 1
+fi
+
+openssl pkeyutl -sign -rawin -inkey "$1" -in "$2" -out "$2.sig"
+
+# The raw public key is the end of the DER encoded key
+public_key=$(openssl pkey -in "$1" -pubout -outform DER | tail -c 32 | od -An -tx1 | tr -d ' \n')
+echo "Wrote $2.sig"
+echo "Public key: $public_key"
@@ -0,0 +1,29 @@
+#!/bin/sh
+# Signs a launcher update archive with an ed25519 private key. Writes the checksum
+# (`<update>.zip.sha256`) and the signature of the archive's SHA-256 digest (`<update>.zip.sig`)
+# next to the archive, both need to be uploaded as release assets. Launchers that have the
+# public key in `updateKeys` in config.yaml only install updates with a valid signature.
+#
+# Create a private key once with:
+#   openssl genpkey -algorithm ed25519 -out hyperbeam_update_key.pem
+#
+# Usage: tools/sign_update.sh <private key> <update.zip>
+set -e
+
+if [ $# -ne 2 ]; then
+    echo "Usage: $0 <private key> <update.zip>" >&2
+    exit 1
+fi
+
+digest_file=$(mktemp)
+trap 'rm -f "$digest_file"' EXIT
+openssl dgst -sha256 -binary "$2" > "$digest_file"
+openssl pkeyutl -sign -rawin -inkey "$1" -in "$digest_file" -out "$2.sig"
+
+hash=$(od -An -tx1 "$digest_file" | tr -d ' \n')
+echo "$hash  $(basename "$2")" > "$2.sha256"
+
+# The raw public key is the end of the DER encoded key
+public_key=$(openssl pkey -in "$1" -pubout -outform DER | tail -c 32 | od -An -tx1 | tr -d ' \n')
+echo "Wrote $2.sha256 and $2.sig"
+echo "Public key: $public_key"